@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS benchmarks (
+    id VARCHAR NOT NULL PRIMARY KEY,
+    created_at TEXT NOT NULL,
+    commit_hash VARCHAR(40) NOT NULL,
+    bench_name VARCHAR NOT NULL,
+    mbps REAL NOT NULL,
+    eps REAL NOT NULL,
+    hist TEXT NOT NULL
+);
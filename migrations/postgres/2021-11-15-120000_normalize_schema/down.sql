@@ -0,0 +1,23 @@
+CREATE TABLE flat_benchmarks (
+    id VARCHAR NOT NULL PRIMARY KEY,
+    created_at TEXT NOT NULL,
+    commit_hash VARCHAR(40) NOT NULL,
+    bench_name VARCHAR NOT NULL,
+    mbps REAL NOT NULL,
+    eps REAL NOT NULL,
+    hist TEXT NOT NULL
+);
+
+INSERT INTO flat_benchmarks (id, created_at, commit_hash, bench_name, mbps, eps, hist)
+    SELECT runs.commit_sha || '-' || benchmarks.name || '-' || runs.started_at,
+        runs.started_at, runs.commit_sha, benchmarks.name, results.mbps, results.eps, results.hist
+    FROM results
+    JOIN runs ON runs.id = results.run_id
+    JOIN benchmarks ON benchmarks.id = results.benchmark_id;
+
+DROP TABLE results;
+DROP TABLE benchmarks;
+DROP TABLE runs;
+DROP TABLE commits;
+ALTER TABLE flat_benchmarks RENAME TO benchmarks;
+ALTER INDEX flat_benchmarks_pkey RENAME TO benchmarks_pkey;
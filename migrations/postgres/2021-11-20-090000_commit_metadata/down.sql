@@ -0,0 +1,4 @@
+DROP INDEX commits_committed_at;
+ALTER TABLE commits DROP COLUMN push_id;
+ALTER TABLE commits DROP COLUMN url;
+DROP TABLE pushes;
@@ -0,0 +1,21 @@
+-- The pushes that queued benchmark jobs, and the commits they contained
+CREATE TABLE pushes (
+    id BIGSERIAL PRIMARY KEY,
+    branch TEXT NOT NULL,
+    before_sha VARCHAR(40) NOT NULL,
+    after_sha VARCHAR(40) NOT NULL,
+    compare_url TEXT,
+    pusher TEXT,
+    received_at TEXT NOT NULL
+);
+CREATE INDEX pushes_after_sha ON pushes (after_sha);
+
+ALTER TABLE commits ADD COLUMN url TEXT;
+ALTER TABLE commits ADD COLUMN push_id BIGINT REFERENCES pushes (id);
+CREATE INDEX commits_push_id ON commits (push_id);
+CREATE INDEX commits_committed_at ON commits (committed_at);
+
+-- Commits stored before only have the time they were first benchmarked
+UPDATE commits SET committed_at = (
+    SELECT MIN(runs.started_at) FROM runs WHERE runs.commit_sha = commits.sha
+) WHERE committed_at IS NULL;
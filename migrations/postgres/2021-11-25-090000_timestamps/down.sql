@@ -0,0 +1,7 @@
+ALTER TABLE runs
+    ALTER COLUMN started_at TYPE TEXT USING to_char(started_at, 'YYYY-MM-DD HH24:MI:SS.US') || ' UTC',
+    ALTER COLUMN finished_at TYPE TEXT USING to_char(finished_at, 'YYYY-MM-DD HH24:MI:SS.US') || ' UTC';
+ALTER TABLE commits
+    ALTER COLUMN committed_at TYPE TEXT USING to_char(committed_at, 'YYYY-MM-DD HH24:MI:SS.US') || ' UTC';
+ALTER TABLE pushes
+    ALTER COLUMN received_at TYPE TEXT USING to_char(received_at, 'YYYY-MM-DD HH24:MI:SS.US') || ' UTC';
@@ -0,0 +1,11 @@
+-- Times used to be stored as text like `2021-10-01 10:00:00.123456789 UTC`.
+-- They become timestamps in UTC, texts without a zone are read as UTC.
+SET LOCAL TimeZone = 'UTC';
+
+ALTER TABLE runs
+    ALTER COLUMN started_at TYPE TIMESTAMP USING started_at::timestamptz AT TIME ZONE 'UTC',
+    ALTER COLUMN finished_at TYPE TIMESTAMP USING finished_at::timestamptz AT TIME ZONE 'UTC';
+ALTER TABLE commits
+    ALTER COLUMN committed_at TYPE TIMESTAMP USING committed_at::timestamptz AT TIME ZONE 'UTC';
+ALTER TABLE pushes
+    ALTER COLUMN received_at TYPE TIMESTAMP USING received_at::timestamptz AT TIME ZONE 'UTC';
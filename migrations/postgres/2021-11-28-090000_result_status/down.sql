@@ -0,0 +1,5 @@
+ALTER TABLE results
+    DROP COLUMN status,
+    DROP COLUMN error,
+    DROP COLUMN stdout,
+    DROP COLUMN stderr;
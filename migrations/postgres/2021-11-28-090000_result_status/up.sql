@@ -0,0 +1,7 @@
+-- Results used to be stored with zeros when a benchmark failed or its
+-- output couldn't be parsed, those are marked as unparseable.
+ALTER TABLE results ADD COLUMN status TEXT NOT NULL DEFAULT 'passed';
+ALTER TABLE results ADD COLUMN error TEXT;
+ALTER TABLE results ADD COLUMN stdout TEXT;
+ALTER TABLE results ADD COLUMN stderr TEXT;
+UPDATE results SET status = 'unparseable' WHERE eps = 0 AND mbps = 0;
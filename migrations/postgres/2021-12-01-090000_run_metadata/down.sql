@@ -0,0 +1,8 @@
+DROP TABLE suite_stats;
+
+ALTER TABLE runs
+    DROP COLUMN allocator,
+    DROP COLUMN tremor_version,
+    DROP COLUMN librdkafka,
+    DROP COLUMN includes,
+    DROP COLUMN excludes;
@@ -0,0 +1,19 @@
+-- What the report says about the build that was benchmarked, and the totals
+-- of each test suite of a run.
+ALTER TABLE runs ADD COLUMN allocator TEXT;
+ALTER TABLE runs ADD COLUMN tremor_version TEXT;
+ALTER TABLE runs ADD COLUMN librdkafka TEXT;
+-- JSON arrays of tags
+ALTER TABLE runs ADD COLUMN includes TEXT;
+ALTER TABLE runs ADD COLUMN excludes TEXT;
+
+CREATE TABLE suite_stats (
+    id BIGSERIAL PRIMARY KEY,
+    run_id BIGINT NOT NULL REFERENCES runs (id) ON DELETE CASCADE,
+    suite TEXT NOT NULL,
+    pass INTEGER NOT NULL,
+    fail INTEGER NOT NULL,
+    skip INTEGER NOT NULL,
+    assertions INTEGER NOT NULL,
+    UNIQUE (run_id, suite)
+);
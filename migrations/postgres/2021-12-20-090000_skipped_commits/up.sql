@@ -0,0 +1,7 @@
+-- Commits the queue skipped because a newer one was pushed while they
+-- waited, for a backfill to benchmark later.
+CREATE TABLE skipped_commits (
+    commit_sha VARCHAR(40) NOT NULL PRIMARY KEY REFERENCES commits (sha) ON DELETE CASCADE,
+    superseded_by VARCHAR(40) NOT NULL,
+    skipped_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,2 @@
+DROP INDEX runs_source;
+ALTER TABLE runs DROP COLUMN source;
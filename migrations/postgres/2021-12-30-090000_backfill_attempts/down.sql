@@ -0,0 +1,2 @@
+ALTER TABLE skipped_commits DROP COLUMN backfilled_at;
+ALTER TABLE skipped_commits DROP COLUMN backfill_attempts;
@@ -1,5 +1,5 @@
 -- Your SQL goes here
-CREATE TABLE benchmarks (
+CREATE TABLE IF NOT EXISTS benchmarks (
     id VARCHAR NOT NULL PRIMARY KEY,
     created_at DATE NOT NULL,
     commit_hash CHAR(40)  NOT NULL,
@@ -0,0 +1,61 @@
+-- Splits the flat benchmarks table into commits, runs, benchmarks and results.
+-- A run is every distinct (commit_hash, created_at) pair of the old table.
+ALTER TABLE benchmarks RENAME TO flat_benchmarks;
+
+CREATE TABLE commits (
+    sha VARCHAR(40) NOT NULL PRIMARY KEY,
+    author TEXT,
+    message TEXT,
+    committed_at TEXT,
+    parent VARCHAR(40),
+    branch TEXT
+);
+
+CREATE TABLE runs (
+    id INTEGER NOT NULL PRIMARY KEY,
+    commit_sha VARCHAR(40) NOT NULL REFERENCES commits (sha),
+    job_id BIGINT,
+    machine TEXT,
+    started_at TEXT NOT NULL,
+    finished_at TEXT,
+    status TEXT NOT NULL
+);
+CREATE INDEX runs_commit_sha ON runs (commit_sha);
+CREATE INDEX runs_started_at ON runs (started_at);
+
+CREATE TABLE benchmarks (
+    id INTEGER NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE results (
+    id INTEGER NOT NULL PRIMARY KEY,
+    run_id BIGINT NOT NULL REFERENCES runs (id) ON DELETE CASCADE,
+    benchmark_id BIGINT NOT NULL REFERENCES benchmarks (id),
+    mbps REAL NOT NULL,
+    eps REAL NOT NULL,
+    hist TEXT NOT NULL,
+    UNIQUE (run_id, benchmark_id)
+);
+CREATE INDEX results_benchmark_id ON results (benchmark_id);
+
+INSERT INTO commits (sha)
+    SELECT DISTINCT commit_hash FROM flat_benchmarks;
+
+INSERT INTO runs (commit_sha, started_at, finished_at, status)
+    SELECT commit_hash, created_at, created_at, 'finished'
+    FROM flat_benchmarks
+    GROUP BY commit_hash, created_at
+    ORDER BY created_at, commit_hash;
+
+INSERT INTO benchmarks (name)
+    SELECT DISTINCT bench_name FROM flat_benchmarks ORDER BY bench_name;
+
+INSERT OR IGNORE INTO results (run_id, benchmark_id, mbps, eps, hist)
+    SELECT runs.id, benchmarks.id, f.mbps, f.eps, f.hist
+    FROM flat_benchmarks f
+    JOIN runs ON runs.commit_sha = f.commit_hash AND runs.started_at = f.created_at
+    JOIN benchmarks ON benchmarks.name = f.bench_name
+    ORDER BY f.created_at, f.id;
+
+DROP TABLE flat_benchmarks;
@@ -0,0 +1,8 @@
+-- Columns are dropped in place, rebuilding `commits` would delete the rows
+-- that reference it when foreign keys are on
+DROP INDEX commits_committed_at;
+DROP INDEX commits_push_id;
+ALTER TABLE commits DROP COLUMN push_id;
+ALTER TABLE commits DROP COLUMN url;
+
+DROP TABLE pushes;
@@ -0,0 +1,3 @@
+UPDATE runs SET started_at = started_at || ' UTC', finished_at = finished_at || ' UTC';
+UPDATE commits SET committed_at = committed_at || ' UTC';
+UPDATE pushes SET received_at = received_at || ' UTC';
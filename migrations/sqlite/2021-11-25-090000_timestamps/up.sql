@@ -0,0 +1,12 @@
+-- Times used to be stored as `2021-10-01 10:00:00.123456789 UTC`, which
+-- doesn't sort by time once the precision differs. SQLite has no datetime
+-- type, so they are rewritten as `YYYY-MM-DD HH:MM:SS.SSS` in UTC, the
+-- format diesel reads and writes for `Timestamp`. Whole seconds drop the
+-- fraction, as diesel does.
+UPDATE runs SET
+    started_at = replace(strftime('%Y-%m-%d %H:%M:%f', replace(started_at, ' UTC', '')), '.000', ''),
+    finished_at = replace(strftime('%Y-%m-%d %H:%M:%f', replace(finished_at, ' UTC', '')), '.000', '');
+UPDATE commits SET
+    committed_at = replace(strftime('%Y-%m-%d %H:%M:%f', replace(committed_at, ' UTC', '')), '.000', '');
+UPDATE pushes SET
+    received_at = replace(strftime('%Y-%m-%d %H:%M:%f', replace(received_at, ' UTC', '')), '.000', '');
@@ -0,0 +1,6 @@
+-- Columns are dropped in place, rebuilding `results` would delete the rows
+-- that reference it when foreign keys are on
+ALTER TABLE results DROP COLUMN stderr;
+ALTER TABLE results DROP COLUMN stdout;
+ALTER TABLE results DROP COLUMN error;
+ALTER TABLE results DROP COLUMN status;
@@ -0,0 +1,9 @@
+DROP TABLE suite_stats;
+
+-- Columns are dropped in place, rebuilding `runs` would delete the rows that
+-- reference it when foreign keys are on
+ALTER TABLE runs DROP COLUMN excludes;
+ALTER TABLE runs DROP COLUMN includes;
+ALTER TABLE runs DROP COLUMN librdkafka;
+ALTER TABLE runs DROP COLUMN tremor_version;
+ALTER TABLE runs DROP COLUMN allocator;
@@ -0,0 +1,3 @@
+-- Dropped in place, rebuilding `results` would delete the rows that
+-- reference it when foreign keys are on
+ALTER TABLE results DROP COLUMN parser_version;
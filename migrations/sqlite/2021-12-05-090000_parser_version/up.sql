@@ -0,0 +1,3 @@
+-- The version of the stdout parser that read a result, so results can be
+-- told apart when the parser changes. Unknown for older results.
+ALTER TABLE results ADD COLUMN parser_version INTEGER;
@@ -0,0 +1,12 @@
+-- Metrics of a result beyond the throughput, e.g. peak RSS or binary size.
+-- `direction` is `higher` or `lower`, whichever is better.
+CREATE TABLE result_metrics (
+    id INTEGER NOT NULL PRIMARY KEY,
+    result_id BIGINT NOT NULL REFERENCES results (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    value DOUBLE NOT NULL,
+    unit TEXT NOT NULL,
+    direction TEXT NOT NULL,
+    UNIQUE (result_id, name)
+);
+CREATE INDEX result_metrics_name ON result_metrics (name);
@@ -0,0 +1,12 @@
+-- Resource usage of the benchmark container sampled while a run was going
+-- on, the IO counters are totals since the container started.
+CREATE TABLE run_samples (
+    id INTEGER NOT NULL PRIMARY KEY,
+    run_id BIGINT NOT NULL REFERENCES runs (id) ON DELETE CASCADE,
+    at TEXT NOT NULL,
+    cpu_percent DOUBLE NOT NULL,
+    rss_bytes BIGINT NOT NULL,
+    read_bytes BIGINT NOT NULL,
+    write_bytes BIGINT NOT NULL
+);
+CREATE INDEX run_samples_run_id ON run_samples (run_id);
@@ -0,0 +1,4 @@
+-- Dropped in place, rebuilding `runs` would delete the rows that reference
+-- it when foreign keys are on
+DROP INDEX runs_source;
+ALTER TABLE runs DROP COLUMN source;
@@ -0,0 +1,5 @@
+-- What queued a run: `main` for pushes to main and ingested reports,
+-- `manual`, `pull_request` or `backfill` otherwise. Only runs of main are
+-- part of trends.
+ALTER TABLE runs ADD COLUMN source TEXT NOT NULL DEFAULT 'main';
+CREATE INDEX runs_source ON runs (source);
@@ -0,0 +1,9 @@
+CREATE TABLE old_skipped_commits (
+    commit_sha VARCHAR(40) NOT NULL PRIMARY KEY REFERENCES commits (sha) ON DELETE CASCADE,
+    superseded_by VARCHAR(40) NOT NULL,
+    skipped_at TEXT NOT NULL
+);
+INSERT INTO old_skipped_commits
+    SELECT commit_sha, superseded_by, skipped_at FROM skipped_commits;
+DROP TABLE skipped_commits;
+ALTER TABLE old_skipped_commits RENAME TO skipped_commits;
@@ -0,0 +1,4 @@
+-- When a backfill of a skipped commit was last tried without getting
+-- benchmarked, so it is retried only after a while, and how often.
+ALTER TABLE skipped_commits ADD COLUMN backfill_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE skipped_commits ADD COLUMN backfilled_at TEXT;
@@ -0,0 +1,119 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shields style SVG badges showing the latest value of a benchmark
+
+use crate::chart::{escape, relative_change, REGRESSION_THRESHOLD};
+use crate::model::{Benchmark, Metric};
+
+const IMPROVED: &str = "#4c1";
+const REGRESSED: &str = "#e05d44";
+const UNCHANGED: &str = "#007ec6";
+const LABEL: &str = "#555";
+
+/// Rough width of a string rendered in 11px Verdana
+fn text_width(s: &str) -> usize {
+    s.chars().count() * 7 + 10
+}
+
+/// Picks the badge color from the change of `latest` against `previous`
+pub fn color(metric: &Metric, latest: &Benchmark, previous: Option<&Benchmark>) -> &'static str {
+    let change = match (metric.get(latest), previous.and_then(|p| metric.get(p))) {
+        (Some(latest), Some(previous)) => {
+            relative_change(latest.direction, previous.value, latest.value)
+        }
+        _ => 0.0,
+    };
+    if change > REGRESSION_THRESHOLD {
+        IMPROVED
+    } else if change < -REGRESSION_THRESHOLD {
+        REGRESSED
+    } else {
+        UNCHANGED
+    }
+}
+
+pub fn render(label: &str, value: &str, color: &str) -> String {
+    let lw = text_width(label);
+    let vw = text_width(value);
+    let w = lw + vw;
+    let label = escape(label);
+    let value = escape(value);
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="20" role="img" aria-label="{label}: {value}"><title>{label}: {value}</title><linearGradient id="s" x2="0" y2="100%"><stop offset="0" stop-color="#bbb" stop-opacity=".1"/><stop offset="1" stop-opacity=".1"/></linearGradient><clipPath id="r"><rect width="{w}" height="20" rx="3" fill="#fff"/></clipPath><g clip-path="url(#r)"><rect width="{lw}" height="20" fill="{label_color}"/><rect x="{lw}" width="{vw}" height="20" fill="{color}"/><rect width="{w}" height="20" fill="url(#s)"/></g><g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11"><text x="{lx}" y="15" fill="#010101" fill-opacity=".3">{label}</text><text x="{lx}" y="14">{label}</text><text x="{vx}" y="15" fill="#010101" fill-opacity=".3">{value}</text><text x="{vx}" y="14">{value}</text></g></svg>"##,
+        w = w,
+        lw = lw,
+        vw = vw,
+        lx = lw / 2,
+        vx = lw + vw / 2,
+        label = label,
+        value = value,
+        label_color = LABEL,
+        color = color,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Direction, MetricValue};
+    use pretty_assertions::assert_eq;
+
+    fn bench(eps: f32) -> Benchmark {
+        Benchmark {
+            metrics: vec![MetricValue::new(
+                "rss",
+                f64::from(eps),
+                "MB",
+                Direction::Lower,
+            )],
+            ..Benchmark::fixture("", eps)
+        }
+    }
+
+    #[test]
+    fn test_color() {
+        assert_eq!(color(&Metric::Eps, &bench(100.0), None), UNCHANGED);
+        assert_eq!(
+            color(&Metric::Eps, &bench(100.0), Some(&bench(99.0))),
+            UNCHANGED
+        );
+        assert_eq!(
+            color(&Metric::Eps, &bench(120.0), Some(&bench(100.0))),
+            IMPROVED
+        );
+        assert_eq!(
+            color(&Metric::Eps, &bench(80.0), Some(&bench(100.0))),
+            REGRESSED
+        );
+        let rss = Metric::from("rss".to_string());
+        assert_eq!(color(&rss, &bench(80.0), Some(&bench(100.0))), IMPROVED);
+        assert_eq!(color(&rss, &bench(120.0), Some(&bench(100.0))), REGRESSED);
+        assert_eq!(
+            color(
+                &Metric::from("size".to_string()),
+                &bench(120.0),
+                Some(&bench(100.0))
+            ),
+            UNCHANGED
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let svg = render("passthrough", "921.6 k events/s", IMPROVED);
+        assert!(svg.contains(r#"aria-label="passthrough: 921.6 k events/s""#));
+        assert!(svg.contains(IMPROVED));
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Build time and binary size of tremor, measured by `Dockerfile.bench`
+//! while building the image and stored as the metrics of the `build`
+//! benchmark so they are trended like any other benchmark.
+
+use crate::model::{Direction, Measurement, MetricValue, ResultStatus};
+use serde::Deserialize;
+
+/// Name of the benchmark the build metrics are stored with
+pub const BENCH: &str = "build";
+
+/// `/build.json` in the benchmark image
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BuildInfo {
+    /// Duration of `cargo build -p tremor-cli --release`
+    pub build_seconds: f64,
+    /// Size of `target/release/tremor`
+    pub binary_bytes: u64,
+}
+
+/// `/bloat.json`, written by `cargo bloat --crates --message-format json`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Bloat {
+    pub crates: Vec<CrateSize>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CrateSize {
+    pub name: String,
+    /// Bytes of the `.text` section taken by the crate
+    pub size: u64,
+}
+
+/// Crate names as metric names, cargo bloat reports code it can't
+/// attribute as `[Unknown]`
+fn metric_name(krate: &str) -> String {
+    let name: String = krate
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || "_-.".contains(*c))
+        .collect();
+    format!("crate_size/{}", name)
+}
+
+/// The `build` result of a commit
+pub fn measurement(info: &BuildInfo, bloat: Option<&Bloat>) -> Measurement {
+    let mut metrics = vec![
+        MetricValue::new("build_time", info.build_seconds, "s", Direction::Lower),
+        MetricValue::new(
+            "binary_size",
+            info.binary_bytes as f64 / 1_000_000.0,
+            "MB",
+            Direction::Lower,
+        ),
+    ];
+    for krate in bloat.map_or(&[][..], |b| &b.crates) {
+        let name = metric_name(&krate.name);
+        if metrics.iter().all(|m| m.name != name) {
+            metrics.push(MetricValue::new(
+                &name,
+                krate.size as f64 / 1000.0,
+                "KB",
+                Direction::Lower,
+            ));
+        }
+    }
+    Measurement {
+        bench_name: BENCH.to_string(),
+        mbps: 0.0,
+        eps: 0.0,
+        hist: String::new(),
+        status: ResultStatus::Passed,
+        error: None,
+        stdout: None,
+        stderr: None,
+        parser_version: None,
+        metrics,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::validate_metrics;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_measurement() -> Result<(), serde_json::Error> {
+        let info: BuildInfo =
+            serde_json::from_str(r#"{"build_seconds": 312.5, "binary_bytes": 84500000}"#)?;
+        let bloat: Bloat = serde_json::from_str(
+            r#"{
+                "file-size": 84500000,
+                "text-section-size": 41000000,
+                "crates": [
+                    {"name": "std", "size": 1200000},
+                    {"name": "tremor_script", "size": 9800000},
+                    {"name": "[Unknown]", "size": 300000}
+                ]
+            }"#,
+        )?;
+
+        let m = measurement(&info, None);
+        assert_eq!(m.bench_name, BENCH);
+        assert_eq!(
+            m.metrics,
+            vec![
+                MetricValue::new("build_time", 312.5, "s", Direction::Lower),
+                MetricValue::new("binary_size", 84.5, "MB", Direction::Lower),
+            ]
+        );
+
+        let m = measurement(&info, Some(&bloat));
+        let names: Vec<&str> = m.metrics.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "build_time",
+                "binary_size",
+                "crate_size/std",
+                "crate_size/tremor_script",
+                "crate_size/Unknown"
+            ]
+        );
+        assert_eq!(m.metrics[3].value, 9800.0);
+        assert_eq!(validate_metrics(&m.metrics), Ok(()));
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Server side rendering of benchmark trend charts as SVG, so they can be
+//! embedded where no JavaScript is available.
+
+use crate::model::{Benchmark, Direction, Metric, MetricValue};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+const WIDTH: f32 = 800.0;
+const HEIGHT: f32 = 320.0;
+const LEFT: f32 = 70.0;
+const RIGHT: f32 = 20.0;
+const TOP: f32 = 40.0;
+const BOTTOM: f32 = 70.0;
+const Y_TICKS: usize = 5;
+const MAX_X_LABELS: usize = 12;
+
+/// Relative change against the previous commit that counts as a regression
+pub const REGRESSION_THRESHOLD: f64 = 0.05;
+
+/// Returns the relative change from `previous` to `current`, normalised so
+/// that a negative value always means the commit got worse.
+pub fn relative_change(direction: Direction, previous: f64, current: f64) -> f64 {
+    if previous == 0.0 {
+        return 0.0;
+    }
+    let change = (current - previous) / previous;
+    match direction {
+        Direction::Higher => change,
+        Direction::Lower => -change,
+    }
+}
+
+pub fn is_regression(direction: Direction, previous: f64, current: f64) -> bool {
+    relative_change(direction, previous, current) < -REGRESSION_THRESHOLD
+}
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Renders a line chart of `metric` for the given benchmark runs, oldest
+/// first. Runs without a custom metric are left out.
+pub fn render(bench_name: &str, metric: &Metric, runs: &[Benchmark]) -> String {
+    let (runs, measured): (Vec<&Benchmark>, Vec<MetricValue>) = runs
+        .iter()
+        .filter_map(|b| metric.get(b).map(|m| (b, m)))
+        .unzip();
+    let unit = measured.last().map_or("", |m| m.unit.as_str());
+    let direction = measured.last().map_or(Direction::Higher, |m| m.direction);
+    let values: Vec<f32> = measured.iter().map(|m| m.value as f32).collect();
+    let max = values.iter().copied().fold(0.0_f32, f32::max);
+    let max = if max > 0.0 { max * 1.1 } else { 1.0 };
+
+    let plot_w = WIDTH - LEFT - RIGHT;
+    let plot_h = HEIGHT - TOP - BOTTOM;
+    let x = |i: usize| {
+        if values.len() > 1 {
+            LEFT + i as f32 * plot_w / (values.len() - 1) as f32
+        } else {
+            LEFT + plot_w / 2.0
+        }
+    };
+    let y = |v: f32| TOP + plot_h - v / max * plot_h;
+
+    let mut svg = String::new();
+    // writing to a String can't fail
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="Verdana,sans-serif" font-size="11">"#,
+        w = WIDTH,
+        h = HEIGHT
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect width="100%" height="100%" fill="#fff"/><text x="{}" y="22" font-size="14" fill="#24292f">{} &#8212; {} ({})</text>"##,
+        LEFT,
+        escape(bench_name),
+        escape(metric.name()),
+        escape(unit)
+    );
+
+    // y axis with grid lines
+    for t in 0..=Y_TICKS {
+        let v = max * t as f32 / Y_TICKS as f32;
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{x1}" y1="{y:.1}" x2="{x2}" y2="{y:.1}" stroke="#eaeef2"/><text x="{tx}" y="{ty:.1}" text-anchor="end" fill="#57606a">{v:.1}</text>"##,
+            x1 = LEFT,
+            x2 = LEFT + plot_w,
+            y = y(v),
+            tx = LEFT - 6.0,
+            ty = y(v) + 4.0,
+            v = v
+        );
+    }
+    let _ = writeln!(
+        svg,
+        r##"<text transform="translate(16 {}) rotate(-90)" text-anchor="middle" fill="#57606a">{}</text>"##,
+        TOP + plot_h / 2.0,
+        escape(unit)
+    );
+    let _ = writeln!(
+        svg,
+        r##"<line x1="{l}" y1="{b}" x2="{r}" y2="{b}" stroke="#57606a"/><line x1="{l}" y1="{t}" x2="{l}" y2="{b}" stroke="#57606a"/>"##,
+        l = LEFT,
+        r = LEFT + plot_w,
+        t = TOP,
+        b = TOP + plot_h
+    );
+
+    // x axis labels, thinned out so they don't overlap
+    let step = runs.len().div_ceil(MAX_X_LABELS);
+    for (i, run) in runs.iter().enumerate().step_by(step.max(1)) {
+        let _ = writeln!(
+            svg,
+            r##"<text transform="translate({:.1} {}) rotate(-45)" text-anchor="end" fill="#57606a">{}</text>"##,
+            x(i),
+            TOP + plot_h + 14.0,
+            escape(run.commit_hash.get(..7).unwrap_or(&run.commit_hash))
+        );
+    }
+
+    if values.is_empty() {
+        let _ = writeln!(
+            svg,
+            r##"<text x="{}" y="{}" text-anchor="middle" fill="#57606a">no data</text>"##,
+            LEFT + plot_w / 2.0,
+            TOP + plot_h / 2.0
+        );
+    } else {
+        let points: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("{:.1},{:.1}", x(i), y(*v)))
+            .collect();
+        let _ = writeln!(
+            svg,
+            r##"<polyline points="{}" fill="none" stroke="#0969da" stroke-width="2"/>"##,
+            points.join(" ")
+        );
+    }
+
+    for (i, (run, v)) in runs.iter().zip(&values).enumerate() {
+        let regression =
+            i > 0 && is_regression(direction, measured[i - 1].value, measured[i].value);
+        let (r, fill) = if regression {
+            (5.0, "#cf222e")
+        } else {
+            (3.0, "#0969da")
+        };
+        let _ = write!(
+            svg,
+            r#"<circle cx="{:.1}" cy="{:.1}" r="{}" fill="{}"><title>{}: {:.2} {}"#,
+            x(i),
+            y(*v),
+            r,
+            fill,
+            escape(&run.commit_hash),
+            v,
+            escape(unit)
+        );
+        if regression {
+            let _ = write!(
+                svg,
+                " (regression: {:+.1}%)",
+                relative_change(direction, measured[i - 1].value, measured[i].value) * 100.0
+            );
+        }
+        let _ = writeln!(svg, "</title></circle>");
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// How many charts are cached by default
+const CACHE_CAPACITY: usize = 256;
+
+/// benchmark name, metric and number of runs
+type ChartKey = (String, Metric, i64);
+
+#[derive(Default)]
+struct Charts {
+    /// commit and chart, with when the entry was last used
+    entries: HashMap<ChartKey, (String, String, u64)>,
+    uses: u64,
+}
+
+/// Rendered charts, keyed by benchmark, metric and window size. Each entry
+/// remembers the latest commit it was rendered for and is replaced once a
+/// newer commit shows up. When full, the least recently used chart is
+/// evicted.
+pub struct ChartCache {
+    charts: Mutex<Charts>,
+    capacity: usize,
+}
+
+impl Default for ChartCache {
+    fn default() -> Self {
+        Self::with_capacity(CACHE_CAPACITY)
+    }
+}
+
+impl ChartCache {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            charts: Mutex::default(),
+            capacity,
+        }
+    }
+
+    pub fn get(
+        &self,
+        bench_name: &str,
+        metric: &Metric,
+        last: i64,
+        commit: &str,
+    ) -> Option<String> {
+        let mut charts = self.charts.lock().unwrap_or_else(|e| e.into_inner());
+        charts.uses += 1;
+        let uses = charts.uses;
+        let (c, svg, used) =
+            charts
+                .entries
+                .get_mut(&(bench_name.to_string(), metric.clone(), last))?;
+        if c != commit {
+            return None;
+        }
+        *used = uses;
+        Some(svg.clone())
+    }
+
+    pub fn insert(&self, bench_name: &str, metric: &Metric, last: i64, commit: &str, svg: String) {
+        let mut charts = self.charts.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (bench_name.to_string(), metric.clone(), last);
+        if !charts.entries.contains_key(&key) && charts.entries.len() >= self.capacity {
+            let oldest = charts
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, _, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                charts.entries.remove(&oldest);
+            }
+        }
+        charts.uses += 1;
+        let uses = charts.uses;
+        charts.entries.insert(key, (commit.to_string(), svg, uses));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_render() {
+        let runs = vec![
+            Benchmark::fixture("aaaaaaa1", 900.0),
+            Benchmark::fixture("bbbbbbb2", 910.0),
+            Benchmark::fixture("ccccccc3", 700.0),
+        ];
+        let svg = render("<pass>", &Metric::Eps, &runs);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("&lt;pass&gt;"));
+        assert!(svg.contains("bbbbbbb"));
+        assert_eq!(svg.matches("regression").count(), 1);
+        assert!(svg.contains("ccccccc3: 700.00 k events/s (regression: -23.1%)"));
+    }
+
+    #[test]
+    fn test_render_custom() {
+        let rss = |commit_hash: &str, value: Option<f64>| Benchmark {
+            metrics: value
+                .map(|v| MetricValue::new("rss", v, "MB", Direction::Lower))
+                .into_iter()
+                .collect(),
+            ..Benchmark::fixture(commit_hash, 900.0)
+        };
+        let runs = vec![
+            rss("aaaaaaa1", Some(100.0)),
+            rss("bbbbbbb2", None),
+            rss("ccccccc3", Some(80.0)),
+            rss("ddddddd4", Some(120.0)),
+        ];
+        let svg = render("passthrough", &Metric::from("rss".to_string()), &runs);
+        assert!(svg.contains("rss (MB)"));
+        assert!(!svg.contains("bbbbbbb2"));
+        assert_eq!(svg.matches("regression").count(), 1);
+        assert!(svg.contains("ddddddd4: 120.00 MB (regression: -50.0%)"));
+    }
+
+    #[test]
+    fn test_cache() {
+        let cache = ChartCache::default();
+        cache.insert("a", &Metric::Eps, 50, "c1", "svg".to_string());
+        assert_eq!(
+            cache.get("a", &Metric::Eps, 50, "c1").as_deref(),
+            Some("svg")
+        );
+        assert_eq!(cache.get("a", &Metric::Eps, 50, "c2"), None);
+        assert_eq!(cache.get("a", &Metric::Mbps, 50, "c1"), None);
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let cache = ChartCache::with_capacity(2);
+        cache.insert("a", &Metric::Eps, 50, "c1", "a".to_string());
+        cache.insert("b", &Metric::Eps, 50, "c1", "b".to_string());
+        assert!(cache.get("a", &Metric::Eps, 50, "c1").is_some());
+        for last in 1..10 {
+            cache.insert("c", &Metric::Eps, last, "c1", "c".to_string());
+        }
+        assert!(cache.get("a", &Metric::Eps, 50, "c1").is_none());
+        assert!(cache.get("b", &Metric::Eps, 50, "c1").is_none());
+        assert_eq!(cache.get("c", &Metric::Eps, 9, "c1").as_deref(), Some("c"));
+        assert_eq!(cache.charts.lock().map(|c| c.entries.len()).ok(), Some(2));
+    }
+}
@@ -0,0 +1,31 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Static assets for the built-in dashboard, embedded into the binary so
+//! it can be served without a separate frontend deployment.
+
+const INDEX_HTML: &str = include_str!("../static/index.html");
+const DASHBOARD_JS: &str = include_str!("../static/dashboard.js");
+const DASHBOARD_CSS: &str = include_str!("../static/dashboard.css");
+
+/// Looks up an embedded asset by request path, returning its content type
+/// and body.
+pub fn asset(path: &str) -> Option<(&'static str, &'static str)> {
+    match path {
+        "/" | "/index.html" => Some(("text/html; charset=utf-8", INDEX_HTML)),
+        "/dashboard.js" => Some(("application/javascript; charset=utf-8", DASHBOARD_JS)),
+        "/dashboard.css" => Some(("text/css; charset=utf-8", DASHBOARD_CSS)),
+        _ => None,
+    }
+}
@@ -0,0 +1,1280 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pooled database access. Diesel is blocking, so queries are run on the
+//! blocking thread pool instead of the async executor.
+//!
+//! SQLite and PostgreSQL are supported, the backend is picked from the
+//! scheme of `DATABASE_URL`. Queries are written once and checked against
+//! each backend by `dispatch!`.
+
+use crate::error::Error;
+use crate::jobs::Source;
+use crate::model::{
+    Benchmark, Commit, CommitDetails, CommitInfo, Failure, Measurement, MetricValue, NewPush,
+    NewResult, NewResultMetric, NewRun, NewSample, NewSkippedCommit, NewSuiteStats, Push,
+    PushDetails, Report, ResultStatus, Run, RunDetails, RunStatus, Sample, SkippedCommit,
+    SuiteStats,
+};
+use crate::schema::{
+    benchmarks, commits, pushes, result_metrics, results, run_samples, runs, skipped_commits,
+    suite_stats,
+};
+use crate::time;
+use async_std::task;
+use chrono::NaiveDateTime;
+use diesel::connection::SimpleConnection;
+use diesel::dsl;
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection, PooledConnection};
+use diesel::{Connection as _, PgConnection, SqliteConnection};
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+
+/// How long a request waits for a free connection before failing
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+pub enum Pool {
+    Sqlite(r2d2::Pool<ConnectionManager<SqliteConnection>>),
+    Postgres(r2d2::Pool<ConnectionManager<PgConnection>>),
+}
+
+pub enum Connection {
+    Sqlite(PooledConnection<ConnectionManager<SqliteConnection>>),
+    Postgres(PooledConnection<ConnectionManager<PgConnection>>),
+}
+
+/// Evaluates `$body` with `$c` bound to the backend specific connection
+macro_rules! dispatch {
+    ($connection:expr, |$c:ident| $body:expr) => {
+        match $connection {
+            $crate::db::Connection::Sqlite(c) => {
+                let $c: &diesel::SqliteConnection = c;
+                $body
+            }
+            $crate::db::Connection::Postgres(c) => {
+                let $c: &diesel::PgConnection = c;
+                $body
+            }
+        }
+    };
+}
+pub(crate) use dispatch;
+
+/// Applied to every new SQLite connection, WAL lets readers proceed while
+/// the worker writes results.
+#[derive(Debug)]
+struct Pragmas;
+
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for Pragmas {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        conn.batch_execute(
+            "PRAGMA busy_timeout = 5000; PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL; \
+             PRAGMA foreign_keys = ON;",
+        )
+        .map_err(r2d2::Error::QueryError)
+    }
+}
+
+pub fn database_url() -> Result<String, Error> {
+    env::var("DATABASE_URL").map_err(|_| Error::Internal("DATABASE_URL must be set".to_string()))
+}
+
+/// Connects to PostgreSQL for `postgres://` and `postgresql://` URLs and to
+/// SQLite for everything else, with an optional `sqlite://` prefix.
+pub fn pool(database_url: &str) -> Result<Pool, Error> {
+    if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+        Ok(Pool::Postgres(
+            r2d2::Pool::builder()
+                .connection_timeout(CHECKOUT_TIMEOUT)
+                .build(ConnectionManager::new(database_url))?,
+        ))
+    } else {
+        let path = database_url
+            .strip_prefix("sqlite://")
+            .unwrap_or(database_url);
+        Ok(Pool::Sqlite(
+            r2d2::Pool::builder()
+                .connection_timeout(CHECKOUT_TIMEOUT)
+                .connection_customizer(Box::new(Pragmas))
+                .build(ConnectionManager::new(path))?,
+        ))
+    }
+}
+
+impl Pool {
+    pub fn get(&self) -> Result<Connection, Error> {
+        Ok(match self {
+            Self::Sqlite(p) => Connection::Sqlite(p.get()?),
+            Self::Postgres(p) => Connection::Postgres(p.get()?),
+        })
+    }
+}
+
+/// Runs `f` with a pooled connection on the blocking thread pool
+pub async fn run<F, T>(pool: &Pool, f: F) -> Result<T, Error>
+where
+    F: FnOnce(&Connection) -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    let pool = pool.clone();
+    task::spawn_blocking(move || {
+        let connection = pool.get()?;
+        f(&connection)
+    })
+    .await
+}
+
+/// A migrated in-memory SQLite database
+#[cfg(test)]
+pub fn memory() -> Result<Connection, Error> {
+    let pool = r2d2::Pool::builder()
+        .max_size(1)
+        .build(ConnectionManager::new(":memory:"))?;
+    let connection = Connection::Sqlite(pool.get()?);
+    crate::migrations::run(&connection)?;
+    Ok(connection)
+}
+
+/// Runs `f` in a transaction on `connection`
+pub fn transaction<T, F>(connection: &Connection, f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    dispatch!(connection, |c| c.transaction(f))
+}
+
+type Columns = (
+    results::id,
+    runs::started_at,
+    runs::commit_sha,
+    benchmarks::name,
+    results::mbps,
+    results::eps,
+    results::hist,
+    commits::committed_at,
+    commits::author,
+    commits::message,
+    results::status,
+);
+
+const COLUMNS: Columns = (
+    results::id,
+    runs::started_at,
+    runs::commit_sha,
+    benchmarks::name,
+    results::mbps,
+    results::eps,
+    results::hist,
+    commits::committed_at,
+    commits::author,
+    commits::message,
+    results::status,
+);
+
+type View = dsl::Select<
+    dsl::InnerJoin<
+        dsl::InnerJoin<results::table, dsl::InnerJoin<runs::table, commits::table>>,
+        benchmarks::table,
+    >,
+    Columns,
+>;
+
+/// Results joined with their run, commit and benchmark, loadable as
+/// `Benchmark`
+pub fn view() -> View {
+    results::table
+        .inner_join(runs::table.inner_join(commits::table))
+        .inner_join(benchmarks::table)
+        .select(COLUMNS)
+}
+
+/// Number of results whose metrics are loaded with one query, below the
+/// bound parameter limit of older SQLite versions
+const METRICS_CHUNK: usize = 500;
+
+/// Loads the custom metrics of results read from `view()`
+pub fn with_metrics(
+    connection: &Connection,
+    mut benchmarks: Vec<Benchmark>,
+) -> Result<Vec<Benchmark>, Error> {
+    let mut metrics: HashMap<i64, Vec<MetricValue>> = HashMap::new();
+    for chunk in benchmarks.chunks(METRICS_CHUNK) {
+        let ids: Vec<i64> = chunk.iter().map(|b| b.id).collect();
+        let rows: Vec<(i64, String, f64, String, String)> = dispatch!(connection, |c| {
+            result_metrics::table
+                .filter(result_metrics::result_id.eq_any(&ids))
+                .select((
+                    result_metrics::result_id,
+                    result_metrics::name,
+                    result_metrics::value,
+                    result_metrics::unit,
+                    result_metrics::direction,
+                ))
+                .order(result_metrics::id)
+                .load(c)
+        })?;
+        for (result_id, name, value, unit, direction) in rows {
+            metrics.entry(result_id).or_default().push(MetricValue {
+                name,
+                value,
+                unit,
+                direction: direction.parse().map_err(Error::Internal)?,
+            });
+        }
+    }
+    for b in &mut benchmarks {
+        b.metrics = metrics.remove(&b.id).unwrap_or_default();
+    }
+    Ok(benchmarks)
+}
+
+fn with_metrics_opt(
+    connection: &Connection,
+    benchmark: Option<Benchmark>,
+) -> Result<Option<Benchmark>, Error> {
+    Ok(with_metrics(connection, benchmark.into_iter().collect())?.pop())
+}
+
+/// Only passed results are part of trends
+fn passed() -> dsl::Eq<results::status, &'static str> {
+    results::status.eq(ResultStatus::Passed.as_str())
+}
+
+no_arg_sql_function!(
+    last_insert_rowid,
+    diesel::sql_types::BigInt,
+    "The rowid of the last row inserted on a SQLite connection"
+);
+
+/// Sources of runs of commits on main, backfills benchmark skipped ones
+const MAIN_SOURCES: &[&str] = &[Source::Main.as_str(), Source::Backfill.as_str()];
+
+/// Only runs of main are part of trends, not pull requests or manual runs
+pub fn on_main() -> dsl::EqAny<runs::source, &'static [&'static str]> {
+    runs::source.eq_any(MAIN_SOURCES)
+}
+
+/// The passed results of the most recent commits across all benchmarks,
+/// oldest first
+pub fn recent(connection: &Connection, limit: i64) -> Result<Vec<Benchmark>, Error> {
+    let mut res: Vec<Benchmark> = dispatch!(connection, |c| view()
+        .filter(passed())
+        .filter(on_main())
+        .order((
+            commits::committed_at.desc(),
+            runs::started_at.desc(),
+            results::id.desc(),
+        ))
+        .limit(limit)
+        .load(c))?;
+    res.reverse();
+    with_metrics(connection, res)
+}
+
+/// The passed results of a benchmark for the last `limit` commits, oldest
+/// first
+pub fn history(connection: &Connection, name: &str, limit: i64) -> Result<Vec<Benchmark>, Error> {
+    let mut res: Vec<Benchmark> = dispatch!(connection, |c| view()
+        .filter(benchmarks::name.eq(name))
+        .filter(passed())
+        .filter(on_main())
+        .order((
+            commits::committed_at.desc(),
+            runs::started_at.desc(),
+            results::id.desc(),
+        ))
+        .limit(limit)
+        .load(c))?;
+    res.reverse();
+    with_metrics(connection, res)
+}
+
+/// The latest passed result of a benchmark, optionally restricted to
+/// commits starting with `commit`
+pub fn latest(
+    connection: &Connection,
+    name: &str,
+    commit: Option<&str>,
+) -> Result<Option<Benchmark>, Error> {
+    let res = dispatch!(connection, |c| {
+        let mut query = view()
+            .filter(benchmarks::name.eq(name))
+            .filter(passed())
+            .filter(on_main())
+            .into_boxed();
+        if let Some(commit) = commit {
+            query = query.filter(runs::commit_sha.like(format!("{}%", commit)));
+        }
+        query
+            .order((
+                commits::committed_at.desc(),
+                runs::started_at.desc(),
+                results::id.desc(),
+            ))
+            .first(c)
+            .optional()
+    })?;
+    with_metrics_opt(connection, res)
+}
+
+/// The latest passed result of a benchmark for a commit other than `commit`
+pub fn previous(
+    connection: &Connection,
+    name: &str,
+    commit: &str,
+) -> Result<Option<Benchmark>, Error> {
+    let res = dispatch!(connection, |c| view()
+        .filter(benchmarks::name.eq(name))
+        .filter(runs::commit_sha.ne(commit))
+        .filter(passed())
+        .filter(on_main())
+        .order((
+            commits::committed_at.desc(),
+            runs::started_at.desc(),
+            results::id.desc(),
+        ))
+        .first(c)
+        .optional())?;
+    with_metrics_opt(connection, res)
+}
+
+/// The latest passed result of every benchmark
+pub fn latest_per_bench(connection: &Connection) -> Result<Vec<Benchmark>, Error> {
+    let names: Vec<String> = dispatch!(connection, |c| benchmarks::table
+        .select(benchmarks::name)
+        .order(benchmarks::name)
+        .load(c))?;
+    let mut res = Vec::with_capacity(names.len());
+    for name in names {
+        if let Some(b) = latest(connection, &name, None)? {
+            res.push(b);
+        }
+    }
+    Ok(res)
+}
+
+fn commit_exists(connection: &Connection, sha: &str) -> Result<bool, Error> {
+    let known: i64 = dispatch!(connection, |c| commits::table
+        .filter(commits::sha.eq(sha))
+        .count()
+        .get_result(c))?;
+    Ok(known > 0)
+}
+
+/// The most recent results that didn't pass, newest first
+pub fn failures(
+    connection: &Connection,
+    name: Option<&str>,
+    limit: i64,
+) -> Result<Vec<Failure>, Error> {
+    Ok(dispatch!(connection, |c| {
+        let mut query = results::table
+            .inner_join(runs::table)
+            .inner_join(benchmarks::table)
+            .filter(results::status.ne(ResultStatus::Passed.as_str()))
+            .select((
+                results::id,
+                results::run_id,
+                runs::started_at,
+                runs::commit_sha,
+                benchmarks::name,
+                results::status,
+                results::error,
+                results::stdout,
+                results::stderr,
+            ))
+            .into_boxed();
+        if let Some(name) = name {
+            query = query.filter(benchmarks::name.eq(name));
+        }
+        query
+            .order((runs::started_at.desc(), results::id.desc()))
+            .limit(limit)
+            .load(c)
+    })?)
+}
+
+/// Adds a commit we haven't seen before. Without metadata from a push the
+/// time it was first seen stands in for the commit time.
+pub fn ensure_commit(
+    connection: &Connection,
+    sha: &str,
+    seen_at: NaiveDateTime,
+) -> Result<(), Error> {
+    if !commit_exists(connection, sha)? {
+        dispatch!(connection, |c| diesel::insert_into(commits::table)
+            .values((commits::sha.eq(sha), commits::committed_at.eq(seen_at)))
+            .execute(c))?;
+    }
+    Ok(())
+}
+
+/// Stores the metadata of a commit
+pub fn upsert_commit(connection: &Connection, sha: &str, info: &CommitInfo) -> Result<(), Error> {
+    if !commit_exists(connection, sha)? {
+        dispatch!(connection, |c| diesel::insert_into(commits::table)
+            .values((commits::sha.eq(sha), info))
+            .execute(c))?;
+    } else if info != &CommitInfo::default() {
+        dispatch!(connection, |c| diesel::update(commits::table.find(sha))
+            .set(info)
+            .execute(c))?;
+    }
+    Ok(())
+}
+
+/// Stores a push along with the metadata of the commits in it and returns
+/// its id
+pub fn record_push(
+    connection: &Connection,
+    push: &NewPush<'_>,
+    commits: Vec<(String, CommitInfo)>,
+) -> Result<i64, Error> {
+    transaction(connection, || {
+        dispatch!(connection, |c| diesel::insert_into(pushes::table)
+            .values(push)
+            .execute(c))?;
+        let push_id: i64 = dispatch!(connection, |c| pushes::table
+            .filter(pushes::after_sha.eq(push.after_sha))
+            .filter(pushes::received_at.eq(push.received_at))
+            .select(pushes::id)
+            .order(pushes::id.desc())
+            .first(c))?;
+        for (sha, mut info) in commits {
+            info.push_id = Some(push_id);
+            upsert_commit(connection, &sha, &info)?;
+        }
+        Ok(push_id)
+    })
+}
+
+/// The commit starting with `sha`, the newest one if the prefix is ambiguous
+pub fn commit(connection: &Connection, sha: &str) -> Result<Option<Commit>, Error> {
+    Ok(dispatch!(connection, |c| commits::table
+        .filter(commits::sha.like(format!("{}%", sha)))
+        .order(commits::committed_at.desc())
+        .first(c)
+        .optional())?)
+}
+
+/// A push and the commits it contained
+pub fn push(connection: &Connection, id: i64) -> Result<Option<PushDetails>, Error> {
+    let push: Option<Push> = dispatch!(connection, |c| pushes::table.find(id).first(c).optional())?;
+    let push = match push {
+        Some(push) => push,
+        None => return Ok(None),
+    };
+    let commits = dispatch!(connection, |c| commits::table
+        .filter(commits::push_id.eq(id))
+        .select(commits::sha)
+        .order((commits::committed_at.asc(), commits::sha.asc()))
+        .load(c))?;
+    Ok(Some(PushDetails { push, commits }))
+}
+
+/// A commit along with the push it was part of
+pub fn commit_details(connection: &Connection, sha: &str) -> Result<Option<CommitDetails>, Error> {
+    let commit = match commit(connection, sha)? {
+        Some(commit) => commit,
+        None => return Ok(None),
+    };
+    let push = match commit.push_id {
+        Some(id) => push(connection, id)?,
+        None => None,
+    };
+    Ok(Some(CommitDetails { commit, push }))
+}
+
+/// The id of a benchmark, which is created if it doesn't exist yet
+pub fn benchmark_id(connection: &Connection, name: &str) -> Result<i64, Error> {
+    let find = || -> Result<Option<i64>, Error> {
+        Ok(dispatch!(connection, |c| benchmarks::table
+            .filter(benchmarks::name.eq(name))
+            .select(benchmarks::id)
+            .first(c)
+            .optional())?)
+    };
+    if let Some(id) = find()? {
+        return Ok(id);
+    }
+    dispatch!(connection, |c| diesel::insert_into(benchmarks::table)
+        .values(benchmarks::name.eq(name))
+        .execute(c))?;
+    find()?.ok_or_else(|| Error::Internal(format!("failed to create benchmark `{}`", name)))
+}
+
+/// The run of a commit that started at `started_at`
+pub fn find_run(
+    connection: &Connection,
+    sha: &str,
+    started_at: NaiveDateTime,
+) -> Result<Option<i64>, Error> {
+    Ok(dispatch!(connection, |c| runs::table
+        .filter(runs::commit_sha.eq(sha))
+        .filter(runs::started_at.eq(started_at))
+        .select(runs::id)
+        .order(runs::id.desc())
+        .first(c)
+        .optional())?)
+}
+
+/// Records a run of a commit and returns its id
+pub fn insert_run(connection: &Connection, run: &NewRun<'_>) -> Result<i64, Error> {
+    transaction(connection, || {
+        ensure_commit(connection, run.commit_sha, run.started_at)?;
+        // runs of a commit can start in the same second, so the id can't be
+        // looked up again
+        Ok(match connection {
+            Connection::Sqlite(c) => {
+                diesel::insert_into(runs::table).values(run).execute(&**c)?;
+                diesel::select(last_insert_rowid).get_result(&**c)?
+            }
+            Connection::Postgres(c) => diesel::insert_into(runs::table)
+                .values(run)
+                .returning(runs::id)
+                .get_result(&**c)?,
+        })
+    })
+}
+
+/// Stores the result of a run for a benchmark, returns false if the run
+/// already has a result for it.
+pub fn insert_result(
+    connection: &Connection,
+    run_id: i64,
+    measurement: &Measurement,
+) -> Result<bool, Error> {
+    let benchmark_id = benchmark_id(connection, &measurement.bench_name)?;
+    let found: i64 = dispatch!(connection, |c| results::table
+        .filter(results::run_id.eq(run_id))
+        .filter(results::benchmark_id.eq(benchmark_id))
+        .count()
+        .get_result(c))?;
+    if found > 0 {
+        return Ok(false);
+    }
+    let new = NewResult {
+        run_id,
+        benchmark_id,
+        mbps: measurement.mbps,
+        eps: measurement.eps,
+        hist: &measurement.hist,
+        status: measurement.status.as_str(),
+        error: measurement.error.as_deref(),
+        stdout: measurement.stdout.as_deref(),
+        stderr: measurement.stderr.as_deref(),
+        parser_version: measurement.parser_version,
+    };
+    dispatch!(connection, |c| diesel::insert_into(results::table)
+        .values(&new)
+        .execute(c))?;
+    if !measurement.metrics.is_empty() {
+        let result_id: i64 = dispatch!(connection, |c| results::table
+            .filter(results::run_id.eq(run_id))
+            .filter(results::benchmark_id.eq(benchmark_id))
+            .select(results::id)
+            .first(c))?;
+        let metrics: Vec<NewResultMetric> = measurement
+            .metrics
+            .iter()
+            .map(|m| NewResultMetric {
+                result_id,
+                name: &m.name,
+                value: m.value,
+                unit: &m.unit,
+                direction: m.direction.as_str(),
+            })
+            .collect();
+        dispatch!(connection, |c| diesel::insert_into(result_metrics::table)
+            .values(&metrics)
+            .execute(c))?;
+    }
+    Ok(true)
+}
+
+/// Starts a run of a job on this machine
+pub fn start_run(
+    connection: &Connection,
+    sha: &str,
+    job_id: Option<i64>,
+    source: Source,
+    machine: Option<&str>,
+) -> Result<i64, Error> {
+    insert_run(
+        connection,
+        &NewRun {
+            commit_sha: sha,
+            job_id,
+            machine,
+            started_at: time::now(),
+            finished_at: None,
+            status: RunStatus::Running.as_str(),
+            source: source.as_str(),
+        },
+    )
+}
+
+/// Stores a report of a commit that was benchmarked outside of a job as a
+/// finished run and returns its id
+pub fn ingest(
+    connection: &Connection,
+    sha: &str,
+    machine: Option<&str>,
+    report: &Report,
+) -> Result<i64, Error> {
+    transaction(connection, || {
+        ensure_commit(connection, sha, time::now())?;
+        let run_id = start_run(connection, sha, None, Source::Main, machine)?;
+        finish_run(connection, run_id, Some(report))?;
+        Ok(run_id)
+    })
+}
+
+/// Stores the report of a run and marks it as finished, or as failed if
+/// there is no report.
+pub fn finish_run(
+    connection: &Connection,
+    run_id: i64,
+    report: Option<&Report>,
+) -> Result<(), Error> {
+    transaction(connection, || {
+        if let Some(report) = report {
+            store_report(connection, run_id, report)?;
+        }
+        let status = if report.is_some() {
+            RunStatus::Finished
+        } else {
+            RunStatus::Failed
+        };
+        dispatch!(connection, |c| diesel::update(runs::table.find(run_id))
+            .set((
+                runs::status.eq(status.as_str()),
+                runs::finished_at.eq(time::now()),
+            ))
+            .execute(c))?;
+        Ok(())
+    })
+}
+
+fn store_report(connection: &Connection, run_id: i64, report: &Report) -> Result<(), Error> {
+    for m in &report.measurements {
+        insert_result(connection, run_id, m)?;
+    }
+    let includes = serde_json::to_string(&report.includes)?;
+    let excludes = serde_json::to_string(&report.excludes)?;
+    dispatch!(connection, |c| diesel::update(runs::table.find(run_id))
+        .set((
+            runs::allocator.eq(&report.allocator),
+            runs::tremor_version.eq(&report.tremor_version),
+            runs::librdkafka.eq(&report.librdkafka),
+            runs::includes.eq(&includes),
+            runs::excludes.eq(&excludes),
+        ))
+        .execute(c))?;
+    let suites: Vec<NewSuiteStats> = report
+        .suites
+        .iter()
+        .map(|s| NewSuiteStats {
+            run_id,
+            suite: &s.suite,
+            pass: s.pass,
+            fail: s.fail,
+            skip: s.skip,
+            assertions: s.assertions,
+        })
+        .collect();
+    dispatch!(connection, |c| diesel::insert_into(suite_stats::table)
+        .values(&suites)
+        .execute(c))?;
+    let samples: Vec<NewSample> = report
+        .samples
+        .iter()
+        .map(|s| NewSample {
+            run_id,
+            at: s.at,
+            cpu_percent: s.cpu_percent,
+            rss_bytes: s.rss_bytes,
+            read_bytes: s.read_bytes,
+            write_bytes: s.write_bytes,
+        })
+        .collect();
+    // SQLite limits the number of bound parameters of a statement
+    for chunk in samples.chunks(METRICS_CHUNK / 6) {
+        dispatch!(connection, |c| diesel::insert_into(run_samples::table)
+            .values(chunk)
+            .execute(c))?;
+    }
+    Ok(())
+}
+
+/// The resource usage sampled during a run, oldest first. `None` if there
+/// is no such run.
+pub fn samples(connection: &Connection, run_id: i64) -> Result<Option<Vec<Sample>>, Error> {
+    let found: i64 = dispatch!(connection, |c| runs::table
+        .find(run_id)
+        .count()
+        .get_result(c))?;
+    if found == 0 {
+        return Ok(None);
+    }
+    let samples = dispatch!(connection, |c| run_samples::table
+        .filter(run_samples::run_id.eq(run_id))
+        .select((
+            run_samples::at,
+            run_samples::cpu_percent,
+            run_samples::rss_bytes,
+            run_samples::read_bytes,
+            run_samples::write_bytes,
+        ))
+        .order(run_samples::at)
+        .load(c))?;
+    Ok(Some(samples))
+}
+
+/// Records commits the queue skipped in favour of `superseded_by`
+pub fn record_skipped(
+    connection: &Connection,
+    shas: &[String],
+    superseded_by: &str,
+) -> Result<(), Error> {
+    let skipped_at = time::now();
+    transaction(connection, || {
+        for sha in shas {
+            ensure_commit(connection, sha, skipped_at)?;
+            let skipped = NewSkippedCommit {
+                commit_sha: sha,
+                superseded_by,
+                skipped_at,
+            };
+            let updated = dispatch!(connection, |c| diesel::update(
+                skipped_commits::table.find(sha)
+            )
+            .set(&skipped)
+            .execute(c))?;
+            if updated == 0 {
+                dispatch!(connection, |c| diesel::insert_into(skipped_commits::table)
+                    .values(&skipped)
+                    .execute(c))?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// The skipped commits that haven't been benchmarked, most recently
+/// skipped first
+pub fn skipped(connection: &Connection, limit: i64) -> Result<Vec<SkippedCommit>, Error> {
+    let benchmarked = runs::table
+        .filter(runs::commit_sha.eq(skipped_commits::commit_sha))
+        .filter(runs::status.eq(RunStatus::Finished.as_str()));
+    Ok(dispatch!(connection, |c| skipped_commits::table
+        .filter(diesel::dsl::not(diesel::dsl::exists(benchmarked)))
+        .order((
+            skipped_commits::skipped_at.desc(),
+            skipped_commits::commit_sha
+        ))
+        .limit(limit)
+        .load(c))?)
+}
+
+/// The most recently skipped commit that hasn't been benchmarked, and
+/// whose last backfill, if any, was tried before `retry_before`
+pub fn backfill(
+    connection: &Connection,
+    retry_before: NaiveDateTime,
+) -> Result<Option<SkippedCommit>, Error> {
+    let benchmarked = runs::table
+        .filter(runs::commit_sha.eq(skipped_commits::commit_sha))
+        .filter(runs::status.eq(RunStatus::Finished.as_str()));
+    Ok(dispatch!(connection, |c| skipped_commits::table
+        .filter(diesel::dsl::not(diesel::dsl::exists(benchmarked)))
+        .filter(
+            skipped_commits::backfilled_at
+                .is_null()
+                .or(skipped_commits::backfilled_at.lt(retry_before))
+        )
+        .order((
+            skipped_commits::skipped_at.desc(),
+            skipped_commits::commit_sha
+        ))
+        .first(c)
+        .optional())?)
+}
+
+/// When the earliest tried backfill of a skipped commit that hasn't been
+/// benchmarked was tried
+pub fn first_backfill(connection: &Connection) -> Result<Option<NaiveDateTime>, Error> {
+    let benchmarked = runs::table
+        .filter(runs::commit_sha.eq(skipped_commits::commit_sha))
+        .filter(runs::status.eq(RunStatus::Finished.as_str()));
+    Ok(dispatch!(connection, |c| skipped_commits::table
+        .filter(diesel::dsl::not(diesel::dsl::exists(benchmarked)))
+        .select(diesel::dsl::min(skipped_commits::backfilled_at))
+        .first(c))?)
+}
+
+/// Records that a backfill of a skipped commit was tried
+pub fn record_backfill(connection: &Connection, sha: &str) -> Result<(), Error> {
+    dispatch!(connection, |c| diesel::update(
+        skipped_commits::table.find(sha)
+    )
+    .set((
+        skipped_commits::backfill_attempts.eq(skipped_commits::backfill_attempts + 1),
+        skipped_commits::backfilled_at.eq(time::now()),
+    ))
+    .execute(c))?;
+    Ok(())
+}
+
+/// The most recent runs with the suite totals of their reports, newest
+/// first
+pub fn recent_runs(connection: &Connection, limit: i64) -> Result<Vec<RunDetails>, Error> {
+    let runs: Vec<Run> = dispatch!(connection, |c| runs::table
+        .order((runs::started_at.desc(), runs::id.desc()))
+        .limit(limit)
+        .load(c))?;
+    let ids: Vec<i64> = runs.iter().map(|r| r.id).collect();
+    let suites: Vec<(i64, SuiteStats)> = dispatch!(connection, |c| suite_stats::table
+        .filter(suite_stats::run_id.eq_any(&ids))
+        .select((
+            suite_stats::run_id,
+            (
+                suite_stats::suite,
+                suite_stats::pass,
+                suite_stats::fail,
+                suite_stats::skip,
+                suite_stats::assertions,
+            ),
+        ))
+        .order(suite_stats::id)
+        .load(c))?;
+    let tags = |t: &Option<String>| -> Vec<String> {
+        t.as_deref()
+            .and_then(|t| serde_json::from_str(t).ok())
+            .unwrap_or_default()
+    };
+    Ok(runs
+        .into_iter()
+        .map(|run| RunDetails {
+            includes: tags(&run.includes),
+            excludes: tags(&run.excludes),
+            suites: suites
+                .iter()
+                .filter(|(id, _)| *id == run.id)
+                .map(|(_, s)| s.clone())
+                .collect(),
+            run,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{Cursor, Filter, Format};
+    use crate::model::Direction;
+    use pretty_assertions::assert_eq;
+
+    const NAME: &str = "db-test";
+    const COMMITS: [&str; 2] = ["aa00", "bb00"];
+    /// A commit that is skipped, and only run by a backfill
+    const SKIPPED: &str = "cc00";
+
+    fn measurement() -> Measurement {
+        Measurement {
+            bench_name: NAME.to_string(),
+            mbps: 58.7,
+            eps: 921.6,
+            hist: String::new(),
+            status: ResultStatus::Passed,
+            error: None,
+            stdout: None,
+            stderr: None,
+            parser_version: None,
+            metrics: vec![MetricValue::new("peak_rss", 48.5, "MB", Direction::Lower)],
+        }
+    }
+
+    fn report(measurement: Measurement) -> Report {
+        Report {
+            allocator: Some("snmalloc".to_string()),
+            includes: vec!["bench".to_string()],
+            suites: vec![SuiteStats {
+                suite: "bench".to_string(),
+                pass: 1,
+                fail: 0,
+                skip: 0,
+                assertions: 0,
+            }],
+            measurements: vec![measurement],
+            ..Report::default()
+        }
+    }
+
+    fn day(d: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd(2021, 10, d).and_hms(10, 0, 0)
+    }
+
+    fn commit(b: Option<Benchmark>) -> Option<String> {
+        b.map(|b| b.commit_hash)
+    }
+
+    /// Removes the test data, returns the number of removed runs
+    fn clear(connection: &Connection) -> Result<usize, Error> {
+        let removed = dispatch!(connection, |c| diesel::delete(
+            runs::table.filter(runs::commit_sha.eq_any(&[COMMITS[0], COMMITS[1], SKIPPED]))
+        )
+        .execute(c))?;
+        dispatch!(connection, |c| diesel::delete(
+            commits::table.filter(commits::sha.eq_any(&[COMMITS[0], COMMITS[1], SKIPPED]))
+        )
+        .execute(c))?;
+        dispatch!(connection, |c| diesel::delete(
+            pushes::table.filter(pushes::after_sha.eq_any(&COMMITS[..]))
+        )
+        .execute(c))?;
+        Ok(removed)
+    }
+
+    /// Clears the test data and stores a passed run of each of `COMMITS`,
+    /// returns their ids
+    fn seed(connection: &Connection) -> Result<Vec<i64>, Error> {
+        clear(connection)?;
+        let mut runs = Vec::new();
+        for (i, sha) in COMMITS.iter().enumerate() {
+            let run = insert_run(
+                connection,
+                &NewRun {
+                    commit_sha: sha,
+                    job_id: None,
+                    machine: None,
+                    started_at: day(i as u32 + 1),
+                    finished_at: None,
+                    status: RunStatus::Running.as_str(),
+                    source: Source::Main.as_str(),
+                },
+            )?;
+            finish_run(connection, run, Some(&report(measurement())))?;
+            runs.push(run);
+        }
+        Ok(runs)
+    }
+
+    fn check_trends(connection: &Connection) -> Result<(), Error> {
+        let runs = seed(connection)?;
+        assert!(!insert_result(connection, runs[0], &measurement())?);
+
+        let last = latest(connection, NAME, None)?;
+        assert_eq!(
+            last.as_ref().map(|b| b.metrics.clone()),
+            Some(measurement().metrics)
+        );
+        assert_eq!(commit(last), Some("bb00".into()));
+        assert_eq!(
+            commit(latest(connection, NAME, Some("a"))?),
+            Some("aa00".into())
+        );
+        assert_eq!(
+            commit(previous(connection, NAME, "bb00")?),
+            Some("aa00".into())
+        );
+        let commits: Vec<String> = history(connection, NAME, 10)?
+            .into_iter()
+            .map(|b| b.commit_hash)
+            .collect();
+        assert_eq!(commits, vec!["aa00".to_string(), "bb00".to_string()]);
+        assert!(latest_per_bench(connection)?
+            .iter()
+            .any(|b| b.commit_hash == "bb00"));
+        Ok(())
+    }
+
+    fn check_failures(connection: &Connection) -> Result<(), Error> {
+        seed(connection)?;
+        let failed = start_run(connection, "aa00", Some(1), Source::Main, Some("test"))?;
+        finish_run(connection, failed, None)?;
+        // a later run of `bb00` where the benchmark failed
+        let rerun = start_run(connection, "bb00", Some(2), Source::Main, Some("test"))?;
+        let broken = Measurement {
+            mbps: 0.0,
+            eps: 0.0,
+            status: ResultStatus::Failed,
+            error: Some("1 of 1 checks failed".to_string()),
+            stdout: Some("boom".to_string()),
+            ..measurement()
+        };
+        finish_run(connection, rerun, Some(&report(broken)))?;
+        let runs = recent_runs(connection, 10)?;
+        let run = runs.iter().find(|r| r.run.id == rerun);
+        let run = run.unwrap_or_else(|| panic!("run {} missing", rerun));
+        assert_eq!(run.run.allocator.as_deref(), Some("snmalloc"));
+        assert_eq!(run.includes, vec!["bench".to_string()]);
+        assert_eq!(run.suites, report(measurement()).suites);
+        let failures = failures(connection, Some(NAME), 10)?;
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].stdout.as_deref(), Some("boom"));
+        // the failed result isn't part of the trend
+        let last = latest(connection, NAME, None)?;
+        assert_eq!(last.map(|b| b.eps), Some(measurement().eps));
+
+        let filter = Filter {
+            bench: Some(NAME.to_string()),
+            commit: None,
+        };
+        let mut cursor = Cursor::new(filter, Format::Jsonl)?;
+        let batch = cursor.next_batch(connection)?.unwrap_or_default();
+        // exports include the failed result
+        assert_eq!(batch.iter().filter(|b| **b == b'\n').count(), 3);
+        Ok(())
+    }
+
+    fn check_pushes(connection: &Connection) -> Result<(), Error> {
+        seed(connection)?;
+        // a push saying `aa00` was committed last moves it to the end
+        let push_id = record_push(
+            connection,
+            &NewPush {
+                branch: "main",
+                before_sha: "bb00",
+                after_sha: "aa00",
+                compare_url: None,
+                pusher: Some("octocat"),
+                received_at: day(4),
+            },
+            vec![(
+                "aa00".to_string(),
+                CommitInfo {
+                    author: Some("octocat".to_string()),
+                    committed_at: Some(day(3)),
+                    parent: Some("bb00".to_string()),
+                    ..CommitInfo::default()
+                },
+            )],
+        )?;
+        assert_eq!(commit(latest(connection, NAME, None)?), Some("aa00".into()));
+        let stored = super::commit(connection, "aa")?;
+        assert_eq!(stored.and_then(|c| c.push_id), Some(push_id));
+        let details = commit_details(connection, "aa00")?.and_then(|d| d.push);
+        let details = details.unwrap_or_else(|| panic!("push of aa00 missing"));
+        assert_eq!(details.push.pusher.as_deref(), Some("octocat"));
+        assert_eq!(details.commits, vec!["aa00".to_string()]);
+        Ok(())
+    }
+
+    fn check_skipped(connection: &Connection) -> Result<(), Error> {
+        seed(connection)?;
+        // only skipped commits without a finished run are left to backfill
+        let shas = vec![SKIPPED.to_string(), "aa00".to_string()];
+        record_skipped(connection, &shas, "bb00")?;
+        record_skipped(connection, &shas[..1], "aa00")?;
+        let left: Vec<(String, String)> = skipped(connection, 100)?
+            .into_iter()
+            .filter(|s| shas.contains(&s.commit_sha))
+            .map(|s| (s.commit_sha, s.superseded_by))
+            .collect();
+        assert_eq!(left, vec![(SKIPPED.to_string(), "aa00".to_string())]);
+
+        // a tried backfill is only retried after a while
+        let next = backfill(connection, time::now())?;
+        assert_eq!(next.map(|s| s.commit_sha), Some(SKIPPED.to_string()));
+        record_backfill(connection, SKIPPED)?;
+        assert_eq!(backfill(connection, day(1))?, None);
+        let retry_before = time::now() + chrono::Duration::seconds(1);
+        let next = backfill(connection, retry_before)?;
+        let next = next.unwrap_or_else(|| panic!("{} not retried", SKIPPED));
+        assert_eq!(next.backfill_attempts, 1);
+        assert_eq!(first_backfill(connection)?, next.backfilled_at);
+
+        // a backfill that succeeds fills the gap in the trend
+        let run = start_run(connection, SKIPPED, Some(3), Source::Backfill, None)?;
+        finish_run(connection, run, Some(&report(measurement())))?;
+        assert!(history(connection, NAME, 10)?
+            .iter()
+            .any(|b| b.commit_hash == SKIPPED));
+        assert_eq!(backfill(connection, retry_before)?, None);
+        assert_eq!(first_backfill(connection)?, None);
+        Ok(())
+    }
+
+    fn check_samples(connection: &Connection) -> Result<(), Error> {
+        seed(connection)?;
+        let sampled = Report {
+            samples: vec![Sample {
+                at: day(5),
+                cpu_percent: 183.5,
+                rss_bytes: 129_394_278,
+                read_bytes: 12_300_000,
+                write_bytes: 4_100,
+            }],
+            ..report(measurement())
+        };
+        let ingested = ingest(connection, "aa00", Some("ci"), &sampled)?;
+        assert_eq!(samples(connection, ingested)?, Some(sampled.samples));
+        assert_eq!(samples(connection, -1)?, None);
+        let runs = recent_runs(connection, 10)?;
+        let run = runs.iter().find(|r| r.run.id == ingested);
+        let run = run.unwrap_or_else(|| panic!("run {} missing", ingested));
+        assert_eq!(run.run.status, RunStatus::Finished.as_str());
+        assert_eq!(run.run.machine.as_deref(), Some("ci"));
+        assert_eq!(clear(connection)?, 3);
+        Ok(())
+    }
+
+    fn check_pull_requests(connection: &Connection) -> Result<(), Error> {
+        clear(connection)?;
+        let main = start_run(connection, "aa00", Some(1), Source::Main, None)?;
+        finish_run(connection, main, Some(&report(measurement())))?;
+        // a later run of a pull request with a much higher throughput
+        let pr = start_run(connection, "bb00", Some(2), Source::PullRequest, None)?;
+        let faster = Measurement {
+            eps: 9000.0,
+            ..measurement()
+        };
+        finish_run(connection, pr, Some(&report(faster)))?;
+
+        let last = latest(connection, NAME, None)?;
+        assert_eq!(last.as_ref().map(|b| b.eps), Some(921.6));
+        assert_eq!(commit(last), Some("aa00".into()));
+        assert_eq!(commit(latest(connection, NAME, Some("bb"))?), None);
+        assert_eq!(commit(previous(connection, NAME, "aa00")?), None);
+        assert_eq!(history(connection, NAME, 10)?.len(), 1);
+        let recent = recent(connection, 10)?;
+        assert_eq!(recent.iter().filter(|b| b.bench_name == NAME).count(), 1);
+        let per_bench = latest_per_bench(connection)?;
+        let per_bench: Vec<&str> = per_bench
+            .iter()
+            .filter(|b| b.bench_name == NAME)
+            .map(|b| b.commit_hash.as_str())
+            .collect();
+        assert_eq!(per_bench, vec!["aa00"]);
+        // the run is still listed as such
+        let runs = recent_runs(connection, 10)?;
+        let run = runs.iter().find(|r| r.run.id == pr);
+        let run = run.unwrap_or_else(|| panic!("run {} missing", pr));
+        assert_eq!(run.run.source, Source::PullRequest.as_str());
+        Ok(())
+    }
+
+    fn check_run_ids(connection: &Connection) -> Result<(), Error> {
+        let seeded = seed(connection)?;
+        let run = NewRun {
+            commit_sha: COMMITS[0],
+            job_id: None,
+            machine: None,
+            started_at: day(9),
+            finished_at: None,
+            status: RunStatus::Running.as_str(),
+            source: Source::Manual.as_str(),
+        };
+        // both runs start in the same second
+        let first = insert_run(connection, &run)?;
+        let second = insert_run(connection, &run)?;
+        assert_ne!(first, second);
+        assert!(!seeded.contains(&first) && !seeded.contains(&second));
+        let found: i64 = dispatch!(connection, |c| runs::table
+            .filter(runs::id.eq_any(&[first, second]))
+            .count()
+            .get_result(c))?;
+        assert_eq!(found, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_trends() -> Result<(), Error> {
+        check_trends(&memory()?)
+    }
+
+    #[test]
+    fn test_failures() -> Result<(), Error> {
+        check_failures(&memory()?)
+    }
+
+    #[test]
+    fn test_pushes() -> Result<(), Error> {
+        check_pushes(&memory()?)
+    }
+
+    #[test]
+    fn test_skipped() -> Result<(), Error> {
+        check_skipped(&memory()?)
+    }
+
+    #[test]
+    fn test_samples() -> Result<(), Error> {
+        check_samples(&memory()?)
+    }
+
+    #[test]
+    fn test_pull_requests() -> Result<(), Error> {
+        check_pull_requests(&memory()?)
+    }
+
+    #[test]
+    fn test_run_ids() -> Result<(), Error> {
+        check_run_ids(&memory()?)
+    }
+
+    #[test]
+    fn test_migrate_flat_table() -> Result<(), Error> {
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .build(ConnectionManager::<SqliteConnection>::new(":memory:"))?;
+        let c = pool.get()?;
+        c.batch_execute(crate::migrations::MIGRATIONS[0].up_sql())?;
+        c.batch_execute(
+            "INSERT INTO benchmarks VALUES
+                ('a-x', '2021-10-01 10:00:00 UTC', 'aa00', 'x', 1.0, 2.0, ''),
+                ('a-y', '2021-10-01 10:00:00 UTC', 'aa00', 'y', 3.0, 4.0, ''),
+                ('b-x', '2021-10-02 10:00:00 UTC', 'bb00', 'x', 5.0, 6.0, '');",
+        )?;
+        let connection = Connection::Sqlite(c);
+        crate::migrations::run(&connection)?;
+
+        let rows = recent(&connection, 10)?;
+        let rows: Vec<(&str, &str, f32)> = rows
+            .iter()
+            .map(|b| (b.commit_hash.as_str(), b.bench_name.as_str(), b.eps))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![("aa00", "x", 2.0), ("aa00", "y", 4.0), ("bb00", "x", 6.0)]
+        );
+        let runs: i64 = dispatch!(&connection, |c| runs::table.count().get_result(c))?;
+        assert_eq!(runs, 2);
+        Ok(())
+    }
+
+    /// Needs a PostgreSQL server, run with
+    /// `POSTGRES_TEST_URL=postgres://... cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_postgres() -> Result<(), Error> {
+        let url = env::var("POSTGRES_TEST_URL")
+            .map_err(|_| Error::Internal("POSTGRES_TEST_URL must be set".to_string()))?;
+        let connection = pool(&url)?.get()?;
+        crate::migrations::run(&connection)?;
+        check_trends(&connection)?;
+        check_failures(&connection)?;
+        check_pushes(&connection)?;
+        check_skipped(&connection)?;
+        check_samples(&connection)?;
+        check_pull_requests(&connection)?;
+        check_run_ids(&connection)?;
+        clear(&connection)?;
+        Ok(())
+    }
+}
@@ -13,6 +13,8 @@
 // limitations under the License.
 
 use hmac::crypto_mac::InvalidKeyLength;
+use hyper::{header, Body, Response, StatusCode};
+use serde::Serialize;
 use std::fmt::Display;
 
 impl From<hyper::Error> for Error {
@@ -20,49 +22,220 @@ impl From<hyper::Error> for Error {
         Self::Hyper(e)
     }
 }
+impl From<hyper::http::Error> for Error {
+    fn from(e: hyper::http::Error) -> Self {
+        Self::Internal(format!("failed to build response: {}", e))
+    }
+}
 impl From<&str> for Error {
     fn from(e: &str) -> Self {
-        Self::Text(e.to_string())
+        Self::Internal(e.to_string())
     }
 }
 impl From<InvalidKeyLength> for Error {
     fn from(_: InvalidKeyLength) -> Self {
-        Self::Other("invalid lenght")
+        Self::Internal("invalid key length".to_string())
     }
 }
-
 impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Self {
-        Self::BadRequest(format!("Invalid JSON: {}", e))
+        Self::Json(e)
+    }
+}
+impl From<serde_urlencoded::de::Error> for Error {
+    fn from(e: serde_urlencoded::de::Error) -> Self {
+        Self::Validation(format!("Invalid query: {}", e))
+    }
+}
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Self {
+        Self::Csv(e)
     }
 }
 impl From<std::io::Error> for Error {
-    fn from(_: std::io::Error) -> Self {
-        Self::Other("IO Error")
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
     }
 }
 impl<T> From<async_std::channel::SendError<T>> for Error {
     fn from(_: async_std::channel::SendError<T>) -> Self {
-        Self::Other("send error")
+        Self::Internal("job queue is closed".to_string())
     }
 }
 impl From<diesel::result::Error> for Error {
-    fn from(_: diesel::result::Error) -> Self {
-        Self::Other("diesel error")
+    fn from(e: diesel::result::Error) -> Self {
+        match e {
+            diesel::result::Error::NotFound => Self::NotFound("no such record".to_string()),
+            e => Self::Db(e),
+        }
+    }
+}
+impl From<diesel::r2d2::PoolError> for Error {
+    fn from(e: diesel::r2d2::PoolError) -> Self {
+        Self::Pool(e)
+    }
+}
+impl From<diesel::migration::RunMigrationsError> for Error {
+    fn from(e: diesel::migration::RunMigrationsError) -> Self {
+        Self::Migration(e)
     }
 }
 
 #[derive(Debug)]
 pub enum Error {
-    Other(&'static str),
-    Text(String),
+    /// The request was malformed or failed validation
+    Validation(String),
+    /// The request could not be authenticated
+    Auth(String),
+    /// The requested resource does not exist
+    NotFound(String),
+    /// The client has queued as much as it may
+    Limit(String),
+    /// A database operation failed
+    Db(diesel::result::Error),
+    /// No database connection could be checked out of the pool
+    Pool(diesel::r2d2::PoolError),
+    /// Applying a schema migration failed
+    Migration(diesel::migration::RunMigrationsError),
+    /// Building or running a benchmark failed
+    Runner(String),
+    /// Benchmark output could not be parsed
+    Parse(String),
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    Io(std::io::Error),
     Hyper(hyper::Error),
-    BadRequest(String),
+    Internal(String),
 }
+
+/// The body of every error response
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'static str,
+    message: String,
+    request_id: &'a str,
+}
+
+impl Error {
+    /// A short machine readable name for the kind of error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Validation(_) => "validation",
+            Self::Auth(_) => "auth",
+            Self::NotFound(_) => "not_found",
+            Self::Limit(_) => "limit",
+            Self::Db(_) | Self::Pool(_) | Self::Migration(_) => "database",
+            Self::Runner(_) => "runner",
+            Self::Parse(_) => "parse",
+            Self::Json(e) if e.is_io() => "io",
+            Self::Json(_) => "validation",
+            Self::Csv(_) => "parse",
+            Self::Io(_) => "io",
+            Self::Hyper(_) => "http",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Validation(_) => StatusCode::BAD_REQUEST,
+            Self::Json(e) if !e.is_io() => StatusCode::BAD_REQUEST,
+            Self::Auth(_) => StatusCode::FORBIDDEN,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Limit(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Runner(_) => StatusCode::BAD_GATEWAY,
+            Self::Pool(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Db(_)
+            | Self::Migration(_)
+            | Self::Parse(_)
+            | Self::Json(_)
+            | Self::Csv(_)
+            | Self::Io(_)
+            | Self::Hyper(_)
+            | Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Turns the error into a JSON response tagged with the request id
+    pub fn into_response(self, request_id: &str) -> Response<Body> {
+        let body = ErrorBody {
+            error: self.kind(),
+            message: self.to_string(),
+            request_id,
+        };
+        let body = serde_json::to_string(&body).unwrap_or_else(|_| {
+            format!(
+                r#"{{"error": "internal", "message": "failed to encode error", "request_id": "{}"}}"#,
+                request_id
+            )
+        });
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = self.status();
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        response
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            Self::Validation(e)
+            | Self::Auth(e)
+            | Self::NotFound(e)
+            | Self::Limit(e)
+            | Self::Runner(e)
+            | Self::Parse(e)
+            | Self::Internal(e) => write!(f, "{}", e),
+            Self::Db(e) => write!(f, "database error: {}", e),
+            Self::Pool(e) => write!(f, "database unavailable: {}", e),
+            Self::Migration(e) => write!(f, "migration failed: {}", e),
+            Self::Json(e) => write!(f, "invalid JSON: {}", e),
+            Self::Csv(e) => write!(f, "CSV error: {}", e),
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::Hyper(e) => write!(f, "HTTP error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Db(e) => Some(e),
+            Self::Pool(e) => Some(e),
+            Self::Migration(e) => Some(e),
+            Self::Json(e) => Some(e),
+            Self::Csv(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::Hyper(e) => Some(e),
+            _ => None,
+        }
     }
 }
 
-impl std::error::Error for Error {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_into_response() -> Result<(), Error> {
+        let e = Error::from(diesel::result::Error::NotFound);
+        assert_eq!(e.status(), StatusCode::NOT_FOUND);
+
+        let e = Error::from(serde_json::from_str::<u8>("nope").unwrap_err());
+        assert_eq!(e.status(), StatusCode::BAD_REQUEST);
+
+        let response = Error::Auth("bad hmac".to_string()).into_response("req-1");
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = async_std::task::block_on(hyper::body::to_bytes(response.into_body()))?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(
+            body,
+            serde_json::json!({"error": "auth", "message": "bad hmac", "request_id": "req-1"})
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export of the benchmark history as CSV or JSON Lines. Rows are read and
+//! encoded in batches so the whole table never has to be held in memory.
+
+use crate::db::{self, dispatch, Connection};
+use crate::error::Error;
+use crate::hist;
+use crate::model::{Benchmark, MetricValue, ResultStatus};
+use crate::schema::{benchmarks, results, runs};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+
+/// Number of rows loaded from the database at once
+pub const BATCH_SIZE: i64 = 500;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Jsonl,
+    Csv,
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Jsonl => "application/x-ndjson",
+            Self::Csv => "text/csv",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(Self::Jsonl),
+            "csv" => Ok(Self::Csv),
+            other => Err(format!("unknown format `{}`, expected csv or jsonl", other)),
+        }
+    }
+}
+
+/// Restricts which rows get exported
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Filter {
+    /// Only export this benchmark
+    pub bench: Option<String>,
+    /// Only export commits starting with this hash
+    pub commit: Option<String>,
+}
+
+/// A single exported row, with the latency percentiles parsed out of the
+/// histogram for convenience.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Record {
+    /// The id of the result on the instance it was exported from
+    #[serde(default)]
+    pub id: String,
+    #[serde(with = "crate::time::rfc3339")]
+    pub created_at: NaiveDateTime,
+    pub commit_hash: String,
+    #[serde(default, with = "crate::time::rfc3339_opt")]
+    pub committed_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub message: Option<String>,
+    pub bench_name: String,
+    pub mbps: f32,
+    pub eps: f32,
+    /// `passed`, `failed` or `unparseable`, only passed results are part of
+    /// trends
+    #[serde(default = "passed")]
+    pub status: String,
+    #[serde(default)]
+    pub p50: Option<f64>,
+    #[serde(default)]
+    pub p99: Option<f64>,
+    #[serde(default)]
+    pub p999: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+    pub hist: String,
+    /// Custom metrics as a JSON array, so they fit in a CSV column
+    #[serde(default, with = "metrics_json")]
+    pub metrics: Vec<MetricValue>,
+}
+
+fn passed() -> String {
+    ResultStatus::Passed.as_str().to_string()
+}
+
+/// `#[serde(with = "metrics_json")]`, no metrics are an empty string
+mod metrics_json {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(metrics: &[MetricValue], s: S) -> Result<S::Ok, S::Error> {
+        if metrics.is_empty() {
+            s.serialize_str("")
+        } else {
+            let json = serde_json::to_string(metrics).map_err(serde::ser::Error::custom)?;
+            s.serialize_str(&json)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<MetricValue>, D::Error> {
+        let s = String::deserialize(d)?;
+        if s.is_empty() {
+            Ok(Vec::new())
+        } else {
+            serde_json::from_str(&s).map_err(de::Error::custom)
+        }
+    }
+}
+
+impl From<Benchmark> for Record {
+    fn from(b: Benchmark) -> Self {
+        let points = hist::parse(&b.hist);
+        Self {
+            p50: hist::percentile(&points, 0.5),
+            p99: hist::percentile(&points, 0.99),
+            p999: hist::percentile(&points, 0.999),
+            max: points.last().map(|p| p.value),
+            id: b.id.to_string(),
+            created_at: b.created_at,
+            commit_hash: b.commit_hash,
+            committed_at: b.committed_at,
+            author: b.author,
+            message: b.message,
+            bench_name: b.bench_name,
+            mbps: b.mbps,
+            eps: b.eps,
+            status: b.status,
+            hist: b.hist,
+            metrics: b.metrics,
+        }
+    }
+}
+
+/// Walks through the stored results in `(created_at, id)` order, one
+/// batch at a time.
+pub struct Cursor {
+    filter: Filter,
+    format: Format,
+    after: Option<(NaiveDateTime, i64)>,
+    done: bool,
+}
+
+impl Cursor {
+    /// Fails when the commit of `filter` isn't a hex prefix, it is matched
+    /// with LIKE
+    pub fn new(filter: Filter, format: Format) -> Result<Self, Error> {
+        if let Some(commit) = &filter.commit {
+            if commit.is_empty()
+                || commit.len() > 40
+                || !commit.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                return Err(Error::Validation(format!("invalid commit `{}`", commit)));
+            }
+        }
+        Ok(Self {
+            filter,
+            format,
+            after: None,
+            done: false,
+        })
+    }
+
+    fn load(&self, connection: &Connection) -> Result<Vec<Benchmark>, Error> {
+        let rows = dispatch!(connection, |c| {
+            // imports store every run as one of main
+            let mut query = db::view().filter(db::on_main()).into_boxed();
+            if let Some(bench) = &self.filter.bench {
+                query = query.filter(benchmarks::name.eq(bench));
+            }
+            if let Some(commit) = &self.filter.commit {
+                query = query.filter(runs::commit_sha.like(format!("{}%", commit)));
+            }
+            if let Some((last_created_at, last_id)) = &self.after {
+                query = query.filter(
+                    runs::started_at.gt(last_created_at).or(runs::started_at
+                        .eq(last_created_at)
+                        .and(results::id.gt(last_id))),
+                );
+            }
+            query
+                .order((runs::started_at.asc(), results::id.asc()))
+                .limit(BATCH_SIZE)
+                .load(c)
+        })?;
+        db::with_metrics(connection, rows)
+    }
+
+    /// Loads and encodes the next batch of rows, returns `None` once
+    /// everything was exported.
+    pub fn next_batch(&mut self, connection: &Connection) -> Result<Option<Vec<u8>>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+        let first = self.after.is_none();
+        let rows = self.load(connection)?;
+        if (rows.len() as i64) < BATCH_SIZE {
+            self.done = true;
+        }
+        if let Some(last) = rows.last() {
+            self.after = Some((last.created_at, last.id));
+        } else if !first {
+            return Ok(None);
+        }
+        let records: Vec<Record> = rows.into_iter().map(Record::from).collect();
+        encode(self.format, &records, first).map(Some)
+    }
+}
+
+/// Encodes records, CSV output only starts with a header if `header` is set
+pub fn encode(format: Format, records: &[Record], header: bool) -> Result<Vec<u8>, Error> {
+    match format {
+        Format::Jsonl => {
+            let mut out = Vec::new();
+            for r in records {
+                serde_json::to_writer(&mut out, r)?;
+                out.push(b'\n');
+            }
+            Ok(out)
+        }
+        Format::Csv => {
+            let mut w = csv::WriterBuilder::new()
+                .has_headers(header)
+                .from_writer(Vec::new());
+            for r in records {
+                w.serialize(r)?;
+            }
+            w.into_inner()
+                .map_err(|e| Error::Io(std::io::Error::new(e.error().kind(), e.to_string())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Direction;
+    use chrono::NaiveDate;
+    use pretty_assertions::assert_eq;
+
+    fn record(commit: &str) -> Record {
+        Record::from(Benchmark {
+            id: 1,
+            created_at: NaiveDate::from_ymd(2021, 10, 1).and_hms(10, 0, 0),
+            mbps: 58.7,
+            hist: "        1343 0.00000          1           1.00\n      116735 0.99219   85280988         128.00\n".to_string(),
+            metrics: vec![MetricValue::new("peak_rss", 48.5, "MB", Direction::Lower)],
+            ..Benchmark::fixture(commit, 921.6)
+        })
+    }
+
+    #[test]
+    fn test_encode_jsonl() -> Result<(), Error> {
+        let out = encode(Format::Jsonl, &[record("a"), record("b")], true)?;
+        let out = String::from_utf8_lossy(&out);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let r: Record = serde_json::from_str(lines[1])?;
+        assert_eq!(r, record("b"));
+        assert_eq!(r.p99, Some(116735.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cursor_commit() {
+        for commit in ["", "%", "a_b", &"a".repeat(41)] {
+            let filter = Filter {
+                bench: None,
+                commit: Some(commit.to_string()),
+            };
+            let status = Cursor::new(filter, Format::Csv)
+                .map(|_| ())
+                .map_err(|e| e.status());
+            assert_eq!(status, Err(hyper::StatusCode::BAD_REQUEST), "{}", commit);
+        }
+        let filter = Filter {
+            bench: None,
+            commit: Some("aBc0".to_string()),
+        };
+        assert!(Cursor::new(filter, Format::Csv).is_ok());
+    }
+
+    #[test]
+    fn test_encode_csv() -> Result<(), Error> {
+        let first = encode(Format::Csv, &[record("a")], true)?;
+        let second = encode(Format::Csv, &[record("b")], false)?;
+        let mut all = first;
+        all.extend(second);
+        let mut reader = csv::Reader::from_reader(all.as_slice());
+        let records = reader.deserialize().collect::<Result<Vec<Record>, _>>()?;
+        assert_eq!(records, vec![record("a"), record("b")]);
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The parts of the GitHub push webhook payload we keep
+
+use crate::model::CommitInfo;
+use crate::time;
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+
+/// `before` of a push that created a branch
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+#[derive(Deserialize, Debug, Default)]
+pub struct PushEvent {
+    #[serde(rename = "ref")]
+    pub git_ref: Option<String>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub compare: Option<String>,
+    pub pusher: Option<User>,
+    #[serde(default)]
+    pub commits: Vec<PushCommit>,
+    pub head_commit: Option<PushCommit>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PushCommit {
+    pub id: String,
+    #[serde(default)]
+    pub message: Option<String>,
+    pub timestamp: Option<String>,
+    pub url: Option<String>,
+    pub author: Option<User>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct User {
+    pub name: Option<String>,
+    pub username: Option<String>,
+}
+
+impl User {
+    /// The GitHub login if there is one, the git name otherwise
+    fn display(&self) -> Option<String> {
+        self.username.clone().or_else(|| self.name.clone())
+    }
+}
+
+impl PushEvent {
+    pub fn pusher(&self) -> Option<String> {
+        self.pusher.as_ref().and_then(User::display)
+    }
+
+    /// The commits of the push with their metadata, oldest first. Pushes
+    /// don't tell us the parents, so each commit gets the one pushed before
+    /// it, and the first one the previous head of the branch.
+    pub fn commit_infos(
+        &self,
+        branch: &str,
+        received_at: NaiveDateTime,
+    ) -> Vec<(String, CommitInfo)> {
+        let mut commits = self.commits.clone();
+        if let Some(head) = &self.head_commit {
+            if commits.iter().all(|c| c.id != head.id) {
+                commits.push(head.clone());
+            }
+        }
+        let mut parent = self.before.clone().filter(|b| b != NULL_SHA);
+        commits
+            .into_iter()
+            .map(|c| {
+                let info = CommitInfo {
+                    author: c.author.as_ref().and_then(User::display),
+                    message: c.message,
+                    committed_at: Some(
+                        c.timestamp
+                            .as_deref()
+                            .and_then(time::parse)
+                            .unwrap_or(received_at),
+                    ),
+                    parent: parent.replace(c.id.clone()),
+                    branch: Some(branch.to_string()),
+                    url: c.url,
+                    push_id: None,
+                };
+                (c.id, info)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_commit_infos() -> Result<(), serde_json::Error> {
+        let event: PushEvent = serde_json::from_str(
+            r#"{
+                "ref": "refs/heads/main",
+                "before": "aa00",
+                "after": "cc00",
+                "compare": "https://github.com/tremor-rs/tremor-runtime/compare/aa00...cc00",
+                "pusher": {"name": "octocat", "email": "octocat@example.com"},
+                "commits": [
+                    {"id": "bb00", "message": "first", "timestamp": "2021-10-01T12:00:00+02:00",
+                     "author": {"name": "Octo Cat", "username": "octocat"}},
+                    {"id": "cc00", "message": "second", "timestamp": "2021-10-01T12:30:00+02:00",
+                     "author": {"name": "Octo Cat"}}
+                ],
+                "head_commit": {"id": "cc00", "message": "second"}
+            }"#,
+        )?;
+        assert_eq!(event.pusher().as_deref(), Some("octocat"));
+
+        let received_at = time::parse("2021-10-02T00:00:00Z").unwrap_or_else(time::now);
+        let infos = event.commit_infos("main", received_at);
+        let shas: Vec<&str> = infos.iter().map(|(sha, _)| sha.as_str()).collect();
+        assert_eq!(shas, vec!["bb00", "cc00"]);
+        assert_eq!(infos[0].1.parent.as_deref(), Some("aa00"));
+        assert_eq!(infos[0].1.author.as_deref(), Some("octocat"));
+        assert_eq!(
+            infos[0]
+                .1
+                .committed_at
+                .as_ref()
+                .map(time::format)
+                .as_deref(),
+            Some("2021-10-01T10:00:00Z")
+        );
+        assert_eq!(infos[1].1.parent.as_deref(), Some("bb00"));
+        assert_eq!(infos[1].1.author.as_deref(), Some("Octo Cat"));
+        Ok(())
+    }
+}
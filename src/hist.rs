@@ -0,0 +1,259 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parsing and plotting of the HDR histogram percentile distribution that
+//! tremor prints at the end of a benchmark.
+
+use crate::chart::escape;
+use serde::Serialize;
+use std::fmt::Write;
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 400.0;
+const LEFT: f64 = 80.0;
+const RIGHT: f64 = 20.0;
+const TOP: f64 = 40.0;
+const BOTTOM: f64 = 50.0;
+const Y_TICKS: usize = 5;
+const COLORS: [&str; 2] = ["#0969da", "#cf222e"];
+
+/// A single row of the percentile distribution
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HistPoint {
+    pub value: f64,
+    pub percentile: f64,
+    pub total_count: u64,
+    /// `1/(1-percentile)`, not set for the final row where it is infinite
+    pub inverse: Option<f64>,
+}
+
+/// The percentile distribution of one commit
+#[derive(Serialize, Debug)]
+pub struct Series {
+    pub commit_hash: String,
+    pub p50: Option<f64>,
+    pub p99: Option<f64>,
+    pub p999: Option<f64>,
+    pub points: Vec<HistPoint>,
+}
+
+impl Series {
+    pub fn new(commit_hash: String, hist: &str) -> Self {
+        let points = parse(hist);
+        Self {
+            commit_hash,
+            p50: percentile(&points, 0.5),
+            p99: percentile(&points, 0.99),
+            p999: percentile(&points, 0.999),
+            points,
+        }
+    }
+}
+
+/// Parses the `Value Percentile TotalCount 1/(1-Percentile)` rows of a
+/// histogram, skipping the header and the summary lines.
+pub fn parse(hist: &str) -> Vec<HistPoint> {
+    hist.lines()
+        .filter_map(|line| {
+            let mut cols = line.split_whitespace();
+            let value = cols.next()?.parse().ok()?;
+            let percentile = cols.next()?.parse().ok()?;
+            let total_count = cols.next()?.parse().ok()?;
+            let inverse = cols.next()?;
+            let inverse = if inverse == "inf" {
+                None
+            } else {
+                Some(inverse.parse().ok()?)
+            };
+            if cols.next().is_some() {
+                return None;
+            }
+            Some(HistPoint {
+                value,
+                percentile,
+                total_count,
+                inverse,
+            })
+        })
+        .collect()
+}
+
+/// The first value at or above the given percentile
+pub fn percentile(points: &[HistPoint], p: f64) -> Option<f64> {
+    points.iter().find(|h| h.percentile >= p).map(|h| h.value)
+}
+
+fn label(inverse: f64) -> String {
+    // 1/(1-p) of 10^n is the percentile with n nines
+    let nines = inverse.log10().round() as usize;
+    match nines {
+        0 => "0%".to_string(),
+        1 => "90%".to_string(),
+        2 => "99%".to_string(),
+        n => format!("99.{}%", "9".repeat(n - 2)),
+    }
+}
+
+/// Plots the percentile distribution of one or more commits on the
+/// usual logarithmic `1/(1-percentile)` axis.
+pub fn render(bench_name: &str, series: &[Series]) -> String {
+    let plotted: Vec<Vec<(f64, f64)>> = series
+        .iter()
+        .map(|s| {
+            s.points
+                .iter()
+                .filter_map(|p| Some((p.inverse?.max(1.0).log10(), p.value)))
+                .collect()
+        })
+        .collect();
+    let max_x = plotted
+        .iter()
+        .flatten()
+        .map(|(x, _)| *x)
+        .fold(1.0_f64, f64::max)
+        .ceil();
+    let max_y = plotted
+        .iter()
+        .flatten()
+        .map(|(_, y)| *y)
+        .fold(0.0_f64, f64::max);
+    let max_y = if max_y > 0.0 { max_y * 1.05 } else { 1.0 };
+
+    let plot_w = WIDTH - LEFT - RIGHT;
+    let plot_h = HEIGHT - TOP - BOTTOM;
+    let x = |v: f64| LEFT + v / max_x * plot_w;
+    let y = |v: f64| TOP + plot_h - v / max_y * plot_h;
+
+    let mut svg = String::new();
+    // writing to a String can't fail
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="Verdana,sans-serif" font-size="11">"#,
+        w = WIDTH,
+        h = HEIGHT
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect width="100%" height="100%" fill="#fff"/><text x="{}" y="22" font-size="14" fill="#24292f">{} &#8212; latency by percentile</text>"##,
+        LEFT,
+        escape(bench_name)
+    );
+
+    for t in 0..=Y_TICKS {
+        let v = max_y * t as f64 / Y_TICKS as f64;
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{x1}" y1="{y:.1}" x2="{x2}" y2="{y:.1}" stroke="#eaeef2"/><text x="{tx}" y="{ty:.1}" text-anchor="end" fill="#57606a">{v:.0}</text>"##,
+            x1 = LEFT,
+            x2 = LEFT + plot_w,
+            y = y(v),
+            tx = LEFT - 6.0,
+            ty = y(v) + 4.0,
+            v = v
+        );
+    }
+    for decade in 0..=(max_x as usize) {
+        let d = decade as f64;
+        let _ = writeln!(
+            svg,
+            r##"<line x1="{x:.1}" y1="{t}" x2="{x:.1}" y2="{b}" stroke="#eaeef2"/><text x="{x:.1}" y="{ly}" text-anchor="middle" fill="#57606a">{l}</text>"##,
+            x = x(d),
+            t = TOP,
+            b = TOP + plot_h,
+            ly = TOP + plot_h + 16.0,
+            l = label(10f64.powf(d))
+        );
+    }
+    let _ = writeln!(
+        svg,
+        r##"<text x="{}" y="{}" text-anchor="middle" fill="#57606a">percentile</text>"##,
+        LEFT + plot_w / 2.0,
+        HEIGHT - 8.0
+    );
+
+    for (i, (s, points)) in series.iter().zip(&plotted).enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let line: Vec<String> = points
+            .iter()
+            .map(|(px, py)| format!("{:.1},{:.1}", x(*px), y(*py)))
+            .collect();
+        let _ = writeln!(
+            svg,
+            r##"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"><title>{}</title></polyline>"##,
+            line.join(" "),
+            color,
+            escape(&s.commit_hash)
+        );
+        let _ = writeln!(
+            svg,
+            r##"<rect x="{lx}" y="{ly}" width="10" height="10" fill="{c}"/><text x="{tx}" y="{ty}" fill="#24292f">{h}</text>"##,
+            lx = LEFT + 10.0,
+            ly = TOP + 8.0 + i as f64 * 16.0,
+            c = color,
+            tx = LEFT + 26.0,
+            ty = TOP + 17.0 + i as f64 * 16.0,
+            h = escape(s.commit_hash.get(..7).unwrap_or(&s.commit_hash))
+        );
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const HIST: &str = r#"
+     Value Percentile TotalCount 1/(1-Percentile)
+
+        1343 0.00000          1           1.00
+        8895 0.50000   43774741           2.00
+       51199 0.90625   77931574          10.67
+      116735 0.99219   85280988         128.00
+      892927 1.00000   85952161            inf
+#[Mean       =     18564.86, StdDeviation   =     23434.73]
+#[Max        =       892927, Total count    =     85952161]
+#[Buckets    =           30, SubBuckets     =         3968]"#;
+
+    #[test]
+    fn test_parse() {
+        let points = parse(HIST);
+        assert_eq!(points.len(), 5);
+        assert_eq!(
+            points[1],
+            HistPoint {
+                value: 8895.0,
+                percentile: 0.5,
+                total_count: 43774741,
+                inverse: Some(2.0)
+            }
+        );
+        assert_eq!(points[4].inverse, None);
+        assert_eq!(percentile(&points, 0.99), Some(116735.0));
+        assert_eq!(percentile(&points, 0.5), Some(8895.0));
+    }
+
+    #[test]
+    fn test_render() {
+        let series = vec![
+            Series::new("aaaaaaaa".to_string(), HIST),
+            Series::new("bbbbbbbb".to_string(), HIST),
+        ];
+        let svg = render("passthrough", &series);
+        assert_eq!(svg.matches("<polyline").count(), 2);
+        assert!(svg.contains("99.9%"));
+    }
+}
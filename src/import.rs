@@ -0,0 +1,247 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Import of benchmark history previously written by `export`, e.g. to
+//! merge results from another instance or to restore a backup.
+
+use crate::db::{self, Connection};
+use crate::error::Error;
+use crate::export::{Format, Record};
+use crate::jobs::Source;
+use crate::model::{validate_metrics, CommitInfo, Measurement, NewRun, ResultStatus, RunStatus};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read};
+use tracing::warn;
+
+/// Number of rows inserted per transaction
+pub const BATCH_SIZE: usize = 500;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub inserted: usize,
+    pub skipped: usize,
+    pub invalid: usize,
+}
+
+/// Checks that a record could have been produced by this service
+pub fn validate(record: &Record) -> Result<(), String> {
+    if record.bench_name.is_empty() {
+        return Err("`bench_name` is empty".to_string());
+    }
+    if record.commit_hash.is_empty()
+        || record.commit_hash.len() > 40
+        || !record.commit_hash.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Err(format!("invalid commit hash `{}`", record.commit_hash));
+    }
+    if !record.eps.is_finite() || record.eps < 0.0 {
+        return Err(format!("invalid eps {}", record.eps));
+    }
+    if !record.mbps.is_finite() || record.mbps < 0.0 {
+        return Err(format!("invalid mbps {}", record.mbps));
+    }
+    record.status.parse::<ResultStatus>()?;
+    validate_metrics(&record.metrics)
+}
+
+impl From<Record> for Measurement {
+    fn from(r: Record) -> Self {
+        Self {
+            bench_name: r.bench_name,
+            mbps: r.mbps,
+            eps: r.eps,
+            hist: r.hist,
+            status: r.status.parse().unwrap_or(ResultStatus::Passed),
+            error: None,
+            stdout: None,
+            stderr: None,
+            parser_version: None,
+            metrics: r.metrics,
+        }
+    }
+}
+
+/// Reads all records, a record that can't be decoded is reported with its
+/// line (JSON Lines) or record (CSV) number.
+fn records<'r, R: Read + 'r>(
+    reader: R,
+    format: Format,
+) -> Box<dyn Iterator<Item = Result<Record, String>> + 'r> {
+    match format {
+        Format::Jsonl => Box::new(
+            BufReader::new(reader)
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+                .map(|(n, line)| {
+                    let line = line.map_err(|e| format!("line {}: {}", n + 1, e))?;
+                    serde_json::from_str(&line).map_err(|e| format!("line {}: {}", n + 1, e))
+                }),
+        ),
+        Format::Csv => Box::new(
+            csv::Reader::from_reader(reader)
+                .into_deserialize()
+                .enumerate()
+                .map(|(n, r)| r.map_err(|e| format!("record {}: {}", n + 1, e))),
+        ),
+    }
+}
+
+/// Stores a record with the run of its commit that started at `created_at`,
+/// returns false if that run already has a result for the benchmark.
+fn insert_record(connection: &Connection, record: &Record) -> Result<bool, Error> {
+    let seen_at = record.committed_at.unwrap_or(record.created_at);
+    db::ensure_commit(connection, &record.commit_hash, seen_at)?;
+    let info = CommitInfo {
+        author: record.author.clone(),
+        message: record.message.clone(),
+        committed_at: record.committed_at,
+        ..CommitInfo::default()
+    };
+    db::upsert_commit(connection, &record.commit_hash, &info)?;
+    let run_id = match db::find_run(connection, &record.commit_hash, record.created_at)? {
+        Some(run_id) => run_id,
+        None => db::insert_run(
+            connection,
+            &NewRun {
+                commit_sha: &record.commit_hash,
+                job_id: None,
+                machine: None,
+                started_at: record.created_at,
+                finished_at: Some(record.created_at),
+                status: RunStatus::Finished.as_str(),
+                source: Source::Main.as_str(),
+            },
+        )?,
+    };
+    db::insert_result(connection, run_id, &Measurement::from(record.clone()))
+}
+
+fn insert(connection: &Connection, batch: &[Record], summary: &mut Summary) -> Result<(), Error> {
+    db::transaction(connection, || {
+        for record in batch {
+            if insert_record(connection, record)? {
+                summary.inserted += 1;
+            } else {
+                summary.skipped += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Imports all records read from `reader`, skipping the ones that are
+/// already stored and the ones failing validation.
+pub fn import<R: Read>(
+    connection: &Connection,
+    reader: R,
+    format: Format,
+) -> Result<Summary, Error> {
+    let mut summary = Summary::default();
+    let mut seen = HashSet::new();
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+
+    for record in records(reader, format) {
+        let record = match record.and_then(|r| validate(&r).map(|_| r)) {
+            Ok(record) => record,
+            Err(e) => {
+                warn!(error = %e, "invalid record");
+                summary.invalid += 1;
+                continue;
+            }
+        };
+        let key = (
+            record.commit_hash.clone(),
+            record.bench_name.clone(),
+            record.created_at,
+        );
+        if !seen.insert(key) {
+            summary.skipped += 1;
+            continue;
+        }
+        batch.push(record);
+        if batch.len() == BATCH_SIZE {
+            insert(connection, &batch, &mut summary)?;
+            batch.clear();
+        }
+    }
+    insert(connection, &batch, &mut summary)?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export;
+    use crate::model::{Benchmark, Direction, MetricValue};
+    use chrono::NaiveDate;
+    use pretty_assertions::assert_eq;
+
+    fn record(commit: &str) -> Record {
+        Record::from(Benchmark {
+            id: 1,
+            created_at: NaiveDate::from_ymd(2021, 10, 1).and_hms(10, 0, 0),
+            mbps: 58.7,
+            committed_at: Some(NaiveDate::from_ymd(2021, 9, 30).and_hms(10, 0, 0)),
+            author: Some("octocat".to_string()),
+            message: Some("Fix the thing\n\nIt was broken".to_string()),
+            metrics: vec![MetricValue::new(
+                "binary_size",
+                42.0,
+                "MB",
+                Direction::Lower,
+            )],
+            ..Benchmark::fixture(commit, 921.6)
+        })
+    }
+
+    #[test]
+    fn test_import() -> Result<(), Error> {
+        let connection = db::memory()?;
+        let bad = record("not-a-hash");
+        let records = vec![record("aa"), record("bb"), record("aa"), bad];
+        let mut data = export::encode(Format::Jsonl, &records, true)?;
+        data.extend_from_slice(b"{\"not\": \"a record\"}\n");
+
+        let summary = import(&connection, data.as_slice(), Format::Jsonl)?;
+        assert_eq!(
+            summary,
+            Summary {
+                inserted: 2,
+                skipped: 1,
+                invalid: 2
+            }
+        );
+
+        let data = export::encode(Format::Csv, &records[..2], true)?;
+        let summary = import(&connection, data.as_slice(), Format::Csv)?;
+        assert_eq!(
+            summary,
+            Summary {
+                inserted: 0,
+                skipped: 2,
+                invalid: 0
+            }
+        );
+        let stored = db::recent(&connection, 10)?;
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].author.as_deref(), Some("octocat"));
+        assert_eq!(
+            stored[0].committed_at,
+            Some(NaiveDate::from_ymd(2021, 9, 30).and_hms(10, 0, 0))
+        );
+        assert_eq!(stored[0].metrics, record("aa").metrics);
+        Ok(())
+    }
+}
@@ -0,0 +1,394 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::error::Error;
+use async_std::channel::{bounded, Receiver, Sender};
+use chrono::offset::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// How many finished jobs we keep around for the queue view
+const HISTORY: usize = 100;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+    /// Superseded by a newer commit while queued
+    Skipped,
+}
+
+impl JobStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Finished => "finished",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+            Self::Skipped => "skipped",
+        }
+    }
+
+    pub fn is_done(self) -> bool {
+        matches!(
+            self,
+            Self::Finished | Self::Failed | Self::Cancelled | Self::Skipped
+        )
+    }
+}
+
+/// What queued a job, in order of priority
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Source {
+    /// A push to main
+    Main,
+    /// Posted to `/jobs` by hand
+    Manual,
+    /// Posted to `/jobs` for the head of a pull request
+    PullRequest,
+    /// A skipped commit, run while there is nothing else to do
+    Backfill,
+}
+
+impl Source {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Main => "main",
+            Self::Manual => "manual",
+            Self::PullRequest => "pull_request",
+            Self::Backfill => "backfill",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Job {
+    pub id: u64,
+    pub commit_hash: String,
+    pub source: Source,
+    /// Who asked for a pull request or manual job
+    pub author: Option<String>,
+    pub status: JobStatus,
+    pub queued_at: String,
+    pub started_at: Option<String>,
+    pub finished_at: Option<String>,
+    pub error: Option<String>,
+    /// Why the job was cancelled, set as soon as it is requested
+    pub cancel_reason: Option<String>,
+    /// The commit that was benchmarked instead of a skipped job
+    pub superseded_by: Option<String>,
+}
+
+/// Keeps track of the benchmark jobs that were queued, are running or
+/// have recently finished.
+#[derive(Default)]
+pub struct Jobs {
+    next_id: AtomicU64,
+    jobs: Mutex<VecDeque<Job>>,
+    /// Signals the running jobs to give up
+    cancels: Mutex<HashMap<u64, Sender<()>>>,
+}
+
+impl Jobs {
+    pub fn enqueue(&self, commit_hash: &str, source: Source, author: Option<&str>) -> Job {
+        let job = self.new_job(commit_hash, source, author);
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.push_back(job.clone());
+        job
+    }
+
+    /// Queues a job unless `limit` jobs of the same source and author are
+    /// already queued
+    pub fn try_enqueue(
+        &self,
+        commit_hash: &str,
+        source: Source,
+        author: Option<&str>,
+        limit: usize,
+    ) -> Result<Job, Error> {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let queued = jobs
+            .iter()
+            .filter(|j| j.status == JobStatus::Queued && j.source == source)
+            .filter(|j| j.author.as_deref() == author)
+            .count();
+        if queued >= limit {
+            return Err(Error::Limit(format!(
+                "too many {} jobs of {} queued, the limit is {}",
+                source.as_str(),
+                author.unwrap_or("unknown"),
+                limit
+            )));
+        }
+        let job = self.new_job(commit_hash, source, author);
+        jobs.push_back(job.clone());
+        Ok(job)
+    }
+
+    fn new_job(&self, commit_hash: &str, source: Source, author: Option<&str>) -> Job {
+        Job {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            commit_hash: commit_hash.to_string(),
+            source,
+            author: author.map(ToString::to_string),
+            status: JobStatus::Queued,
+            queued_at: Utc::now().to_rfc3339(),
+            started_at: None,
+            finished_at: None,
+            error: None,
+            cancel_reason: None,
+            superseded_by: None,
+        }
+    }
+
+    /// Marks a queued job as running. Returns what signals its
+    /// cancellation, or `None` if it was cancelled while queued.
+    pub fn start(&self, id: u64) -> Option<Receiver<()>> {
+        let mut started = false;
+        self.update(id, |job| {
+            if job.status == JobStatus::Queued {
+                job.status = JobStatus::Running;
+                job.started_at = Some(Utc::now().to_rfc3339());
+                started = true;
+            }
+        });
+        if !started {
+            return None;
+        }
+        let (tx, rx) = bounded(1);
+        let mut cancels = self.cancels.lock().unwrap_or_else(|e| e.into_inner());
+        cancels.insert(id, tx);
+        Some(rx)
+    }
+
+    /// Records the end of a job, which counts as cancelled if that was
+    /// requested while it ran. Returns its final status.
+    pub fn finish(&self, id: u64, error: Option<String>) -> JobStatus {
+        let mut status = JobStatus::Failed;
+        self.update(id, |job| {
+            job.status = if job.cancel_reason.is_some() {
+                JobStatus::Cancelled
+            } else if error.is_some() {
+                JobStatus::Failed
+            } else {
+                JobStatus::Finished
+            };
+            job.finished_at = Some(Utc::now().to_rfc3339());
+            job.error = error;
+            status = job.status;
+        });
+        let mut cancels = self.cancels.lock().unwrap_or_else(|e| e.into_inner());
+        cancels.remove(&id);
+        drop(cancels);
+        self.trim();
+        status
+    }
+
+    /// Marks a queued job as skipped in favour of the given commit
+    pub fn skip(&self, id: u64, superseded_by: &str) {
+        self.update(id, |job| {
+            if job.status == JobStatus::Queued {
+                job.status = JobStatus::Skipped;
+                job.finished_at = Some(Utc::now().to_rfc3339());
+                job.superseded_by = Some(superseded_by.to_string());
+            }
+        });
+        self.trim();
+    }
+
+    /// Cancels a queued job right away and signals a running one to stop.
+    /// Returns the status the job had and the job, or `None` if it isn't
+    /// known.
+    pub fn cancel(&self, id: u64, reason: &str) -> Option<(JobStatus, Job)> {
+        let mut cancelled = None;
+        self.update(id, |job| {
+            let status = job.status;
+            match status {
+                JobStatus::Queued => {
+                    job.status = JobStatus::Cancelled;
+                    job.finished_at = Some(Utc::now().to_rfc3339());
+                    job.cancel_reason = Some(reason.to_string());
+                }
+                JobStatus::Running => job.cancel_reason = Some(reason.to_string()),
+                _ => {}
+            }
+            cancelled = Some((status, job.clone()));
+        });
+        let (status, job) = cancelled?;
+        if status == JobStatus::Running {
+            let cancels = self.cancels.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(tx) = cancels.get(&id) {
+                // a full channel means it was already signalled
+                let _ = tx.try_send(());
+            }
+        } else {
+            self.trim();
+        }
+        Some((status, job))
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.iter().find(|j| j.id == id).cloned()
+    }
+
+    /// Ids of the running jobs of a source
+    pub fn running(&self, source: Source) -> Vec<u64> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.iter()
+            .filter(|j| j.status == JobStatus::Running && j.source == source)
+            .map(|j| j.id)
+            .collect()
+    }
+
+    pub fn count(&self, status: JobStatus) -> usize {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.iter().filter(|j| j.status == status).count()
+    }
+
+    /// All known jobs, oldest first
+    pub fn list(&self) -> Vec<Job> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.iter().cloned().collect()
+    }
+
+    /// Forgets the oldest jobs that are done beyond the last `HISTORY`
+    fn trim(&self) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        let mut done = jobs.iter().filter(|j| j.status.is_done()).count();
+        jobs.retain(|j| {
+            if done > HISTORY && j.status.is_done() {
+                done -= 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    fn update<F: FnOnce(&mut Job)>(&self, id: u64, f: F) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(job) = jobs.iter_mut().find(|j| j.id == id) {
+            f(job);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_job_lifecycle() {
+        let jobs = Jobs::default();
+        let a = jobs.enqueue("a", Source::Main, None);
+        let b = jobs.enqueue("b", Source::Main, None);
+        assert!(jobs.start(a.id).is_some());
+        assert_eq!(jobs.finish(a.id, None), JobStatus::Finished);
+        assert!(jobs.start(b.id).is_some());
+        assert_eq!(
+            jobs.finish(b.id, Some("boom".to_string())),
+            JobStatus::Failed
+        );
+
+        let list = jobs.list();
+        assert_eq!(list[0].status, JobStatus::Finished);
+        assert_eq!(list[1].status, JobStatus::Failed);
+        assert_eq!(list[1].error.as_deref(), Some("boom"));
+
+        for i in 0..HISTORY {
+            let j = jobs.enqueue(&i.to_string(), Source::Main, None);
+            jobs.finish(j.id, None);
+        }
+        let q = jobs.enqueue("queued", Source::Main, None);
+        let list = jobs.list();
+        assert_eq!(list.len(), HISTORY + 1);
+        assert_eq!(list.last().map(|j| j.id), Some(q.id));
+    }
+
+    #[test]
+    fn test_cancel() {
+        let jobs = Jobs::default();
+        let queued = jobs.enqueue("a", Source::Main, None);
+        let running = jobs.enqueue("b", Source::Main, None);
+        assert!(jobs.cancel(42, "nope").is_none());
+
+        let job = jobs.cancel(queued.id, "superseded");
+        let status = job.map(|(before, j)| (before, j.status));
+        assert_eq!(status, Some((JobStatus::Queued, JobStatus::Cancelled)));
+        assert!(jobs.start(queued.id).is_none());
+
+        let cancel = jobs.start(running.id);
+        let cancel = cancel.unwrap_or_else(|| panic!("job not started"));
+        let job = jobs.cancel(running.id, "hangs");
+        let status = job.map(|(before, j)| (before, j.status));
+        assert_eq!(status, Some((JobStatus::Running, JobStatus::Running)));
+        assert_eq!(cancel.try_recv(), Ok(()));
+        let status = jobs.finish(running.id, Some("job cancelled".to_string()));
+        assert_eq!(status, JobStatus::Cancelled);
+
+        // finished jobs stay as they are
+        let job = jobs.cancel(running.id, "again");
+        let job = job.map(|(before, j)| (before, j.cancel_reason));
+        assert_eq!(job, Some((JobStatus::Cancelled, Some("hangs".to_string()))));
+        let list = jobs.list();
+        assert_eq!(list[0].cancel_reason.as_deref(), Some("superseded"));
+        assert_eq!(jobs.count(JobStatus::Cancelled), 2);
+
+        let skipped = jobs.enqueue("c", Source::Main, None);
+        jobs.skip(skipped.id, "d");
+        jobs.skip(running.id, "d");
+        assert_eq!(
+            jobs.get(skipped.id).map(|j| j.status),
+            Some(JobStatus::Skipped)
+        );
+        assert_eq!(
+            jobs.get(running.id).map(|j| j.status),
+            Some(JobStatus::Cancelled)
+        );
+        assert!(jobs.start(skipped.id).is_none());
+    }
+
+    #[test]
+    fn test_sources() {
+        let jobs = Jobs::default();
+        let backfill = jobs.enqueue("d", Source::Backfill, None);
+        assert!(jobs.running(Source::Backfill).is_empty());
+        let _cancel = jobs.start(backfill.id);
+        assert_eq!(jobs.running(Source::Backfill), vec![backfill.id]);
+    }
+
+    #[test]
+    fn test_try_enqueue() {
+        let jobs = Jobs::default();
+        let pr = |author| jobs.try_enqueue("a", Source::PullRequest, Some(author), 2);
+        assert!(pr("octocat").is_ok());
+        assert!(pr("octocat").is_ok());
+        assert!(matches!(pr("octocat"), Err(Error::Limit(_))));
+        assert!(pr("hubot").is_ok());
+        // only queued jobs count towards the limit
+        let _cancel = jobs.start(0);
+        assert!(pr("octocat").is_ok());
+        assert_eq!(jobs.count(JobStatus::Queued), 3);
+    }
+}
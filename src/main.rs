@@ -12,30 +12,59 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+// diesel 1.x derives and `table!` expand to impls nested in functions
+#![allow(non_local_definitions)]
+
 #[macro_use]
 extern crate diesel;
 
+mod badge;
+mod build;
+mod chart;
+mod dashboard;
+mod db;
 mod error;
+mod export;
+mod github;
+mod hist;
+mod import;
+mod jobs;
+mod metrics;
+mod migrations;
 mod model;
+mod output;
+mod queue;
+mod report;
+mod resources;
 pub(crate) mod schema;
+mod time;
 mod util;
 
+use crate::chart::ChartCache;
 use crate::error::Error;
-use crate::schema::benchmarks;
-use crate::schema::benchmarks::dsl::*;
-use crate::util::convert_into_relevant_data;
-use async_std::channel::{bounded, Sender};
+use crate::jobs::{Job, JobStatus, Jobs, Source};
+use crate::metrics::{Metrics, Phase};
+use async_std::channel::{bounded, Receiver, Sender};
+use async_std::prelude::FutureExt;
 use async_std::task;
-use clap::{crate_authors, crate_version, Clap};
-use color_eyre::eyre::Result;
-use diesel::prelude::*;
-use diesel::{Connection, SqliteConnection};
-use model::Benchmark;
-use serde_json::Value;
-
-use async_std::process::Command;
+use clap::{crate_authors, crate_version, Clap, IntoApp};
+use futures_util::stream;
+use model::{Measurement, Metric, NewPush, Report, Sample};
+use percent_encoding::percent_decode_str;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use async_std::process::{Command, Output};
 use async_std::sync::Arc;
-use std::env;
+use std::collections::{HashSet, VecDeque};
+use std::fs::File;
+use std::future::Future;
+use std::io::{BufWriter, Read, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use tracing_subscriber::EnvFilter;
 
 use hyper::service::{make_service_fn, service_fn};
 use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
@@ -48,207 +77,1404 @@ type HmacSha256 = Hmac<Sha256>;
 #[derive(Clap, Debug, Clone)]
 #[clap(version = crate_version!(), author = crate_authors!())]
 struct Opts {
+    /// Log output format, `text` or `json`
+    #[clap(long, default_value = "text")]
+    log_format: LogFormat,
+    #[clap(subcommand)]
+    command: SubCommand,
+}
+
+/// Inserts `serve` when no subcommand is given, so `tremor-benchmark [key]`
+/// keeps working as before there were subcommands. Returns whether it did.
+fn with_default_subcommand(mut args: Vec<String>) -> (Vec<String>, bool) {
+    let app = Opts::into_app();
+    let mut i = 1;
+    while let Some(arg) = args.get(i) {
+        if arg == "--log-format" {
+            i += 2;
+        } else if arg.starts_with("--log-format=") {
+            i += 1;
+        } else if arg.starts_with('-') {
+            // help, version or a mistake for clap to report
+            return (args, false);
+        } else {
+            break;
+        }
+    }
+    let known = args
+        .get(i)
+        .is_some_and(|arg| arg == "help" || app.get_subcommands().any(|s| s.get_name() == arg));
+    if known || i > args.len() {
+        return (args, false);
+    }
+    args.insert(i, "serve".to_string());
+    (args, true)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown log format `{}`, expected text or json",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Clap, Debug, Clone)]
+enum SubCommand {
+    /// Run the benchmark server
+    Serve(ServeOpts),
+    /// Export the benchmark history as CSV or JSON Lines
+    Export(ExportOpts),
+    /// Import benchmark history written by `export`
+    Import(ImportOpts),
+    /// Inspect or apply the database schema migrations
+    Migrate(MigrateOpts),
+    /// Store a benchmark report of a commit that was run elsewhere
+    Ingest(IngestOpts),
+    /// Cancel a queued or running job of a server
+    Cancel(CancelOpts),
+}
+
+#[derive(Clap, Debug, Clone)]
+struct ServeOpts {
     /// The key to validate github with
     key: Option<String>,
+    /// Don't apply pending database migrations on startup
+    #[clap(long)]
+    no_migrate: bool,
+    /// Name of the machine the benchmarks run on, recorded with every run
+    #[clap(long, env = "BENCHMARK_MACHINE")]
+    machine: Option<String>,
+    /// Record the size of each crate in the tremor binary with `cargo bloat`
+    #[clap(long)]
+    crate_sizes: bool,
+    /// Seconds between resource usage samples of the benchmark container,
+    /// 0 disables sampling
+    #[clap(long, default_value = "5")]
+    sample_interval: u64,
+    /// Seconds after which building the benchmark image is given up,
+    /// 0 for no limit
+    #[clap(long, default_value = "3600")]
+    build_timeout: u64,
+    /// Seconds after which running the benchmarks is given up, 0 for no
+    /// limit
+    #[clap(long, default_value = "3600")]
+    run_timeout: u64,
+    /// `fifo` runs every queued job, `coalesce` only the newest of the
+    /// ones that queued up and records the others as skipped
+    #[clap(long, default_value = "fifo")]
+    queue: queue::Policy,
+    /// How many of the skipped jobs `coalesce` runs anyway, evenly spaced
+    #[clap(long, default_value = "0")]
+    sample_skipped: usize,
+    /// How many pull request jobs an author may have queued, 0 for no
+    /// limit
+    #[clap(long, default_value = "2")]
+    max_pr_jobs: usize,
+    /// Benchmark skipped commits while there is nothing else to do
+    #[clap(long)]
+    backfill: bool,
+    /// Seconds after which a backfill that didn't benchmark a commit is
+    /// tried again
+    #[clap(long, default_value = "86400", parse(try_from_str = positive))]
+    backfill_retry: u64,
+}
+
+/// Parses a number of seconds that can't be 0
+fn positive(s: &str) -> Result<u64, String> {
+    match s.parse() {
+        Ok(0) => Err("must be greater than 0".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Clap, Debug, Clone)]
+struct CancelOpts {
+    /// Id of the job
+    id: u64,
+    /// Why the job is cancelled, recorded with it
+    #[clap(long, default_value = "cancelled")]
+    reason: String,
+    /// Address of the server
+    #[clap(long, default_value = "http://127.0.0.1:8080")]
+    url: String,
+    /// Key the server validates requests with
+    #[clap(long)]
+    key: Option<String>,
+}
+
+#[derive(Clap, Debug, Clone)]
+struct IngestOpts {
+    /// Report to read, `-` for stdin
+    input: String,
+    /// Commit the report belongs to
+    #[clap(long)]
+    commit: String,
+    /// `tremor`, `criterion`, `hyperfine` or `generic`, detected by default
+    #[clap(long)]
+    format: Option<report::Format>,
+    /// Name of the benchmark of a single criterion estimate, prefixes
+    /// hyperfine commands
+    #[clap(long)]
+    name: Option<String>,
+    /// Name of the machine the report was produced on
+    #[clap(long)]
+    machine: Option<String>,
+}
+
+#[derive(Clap, Debug, Clone)]
+struct MigrateOpts {
+    #[clap(subcommand)]
+    command: MigrateCommand,
+}
+
+#[derive(Clap, Debug, Clone)]
+enum MigrateCommand {
+    /// List the migrations and whether they have been applied
+    Status,
+    /// Apply all pending migrations
+    Run,
 }
 
-async fn get_report(hash: &str) -> Result<Vec<Benchmark>> {
+#[derive(Clap, Debug, Clone)]
+struct ExportOpts {
+    /// Output format, `csv` or `jsonl`
+    #[clap(long, default_value = "jsonl")]
+    format: export::Format,
+    /// Only export this benchmark
+    #[clap(long)]
+    bench: Option<String>,
+    /// Only export commits starting with this hash
+    #[clap(long)]
+    commit: Option<String>,
+    /// File to write to, defaults to stdout
+    #[clap(long, short)]
+    output: Option<String>,
+}
+
+#[derive(Clap, Debug, Clone)]
+struct ImportOpts {
+    /// File to read from, `-` for stdin
+    input: String,
+    /// Input format, `csv` or `jsonl`, guessed from the file extension by default
+    #[clap(long)]
+    format: Option<export::Format>,
+}
+
+/// Header carrying the id used to correlate a request with its logs
+const REQUEST_ID: &str = "x-request-id";
+
+/// Shared state of the HTTP service
+struct State {
+    opts: ServeOpts,
+    jobs: Jobs,
+    charts: ChartCache,
+    metrics: Metrics,
+    pool: db::Pool,
+    tx: Sender<Job>,
+    requests: AtomicU64,
+}
+
+impl State {
+    /// The id a client sent along with the request, or a new one
+    fn request_id(&self, req: &Request<Body>) -> String {
+        req.headers()
+            .get(REQUEST_ID)
+            .and_then(|v| v.to_str().ok())
+            .map(ToString::to_string)
+            .unwrap_or_else(|| {
+                format!(
+                    "{:x}-{:x}",
+                    std::process::id(),
+                    self.requests.fetch_add(1, Ordering::Relaxed)
+                )
+            })
+    }
+}
+
+#[derive(Deserialize)]
+struct ChartQuery {
+    #[serde(default)]
+    metric: Metric,
+    #[serde(default = "default_last")]
+    last: i64,
+}
+
+#[derive(Deserialize)]
+struct BadgeQuery {
+    #[serde(default)]
+    metric: Metric,
+}
+
+/// Query of `/runs` and `/skipped`
+#[derive(Deserialize)]
+struct RunsQuery {
+    #[serde(default = "default_last")]
+    last: i64,
+}
+
+/// Body of `POST /jobs`
+#[derive(Deserialize)]
+struct NewJob {
+    commit: String,
+    /// `manual` or `pull_request`
+    #[serde(default = "default_source")]
+    source: Source,
+    author: Option<String>,
+}
+
+fn default_source() -> Source {
+    Source::Manual
+}
+
+/// Body of `DELETE /jobs/{id}`
+#[derive(Deserialize, Serialize)]
+struct CancelBody {
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct IngestQuery {
+    commit: String,
+    format: Option<report::Format>,
+    name: Option<String>,
+    machine: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FailuresQuery {
+    /// Only failures of this benchmark
+    bench: Option<String>,
+    #[serde(default = "default_last")]
+    last: i64,
+}
+
+#[derive(Deserialize)]
+struct HistQuery {
+    /// Commit (or prefix) to plot, defaults to the latest one
+    commit: Option<String>,
+    /// Second commit (or prefix) to compare against
+    compare: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: export::Format,
+    #[serde(flatten)]
+    filter: export::Filter,
+}
+
+fn default_last() -> i64 {
+    50
+}
+
+/// Most runs we render in a single chart
+const MAX_LAST: i64 = 1000;
+
+/// Runs docker with the given arguments, failing with the end of its
+/// stderr if it exits unsuccessfully.
+async fn docker(args: &[&str]) -> Result<Output, Error> {
+    let output = Command::new("docker")
+        .args(args)
+        // so giving up on a phase stops it
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| Error::Runner(format!("failed to run docker: {}", e)))?;
+    if output.status.success() {
+        Ok(output)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let tail: Vec<&str> = stderr.lines().rev().take(10).collect();
+        let tail: Vec<&str> = tail.into_iter().rev().collect();
+        Err(Error::Runner(format!(
+            "`docker {}` failed with {}: {}",
+            args.first().unwrap_or(&""),
+            output.status,
+            tail.join("\n")
+        )))
+    }
+}
+
+/// Reads a JSON file out of the benchmark image
+async fn read_image_json<T: DeserializeOwned>(tag: &str, path: &str) -> Result<T, Error> {
+    let out = docker(&["run", "--rm", tag, "cat", path]).await?;
+    serde_json::from_slice(&out.stdout)
+        .map_err(|e| Error::Parse(format!("invalid {}: {}", path, e)))
+}
+
+/// The build time and binary size `Dockerfile.bench` recorded in the image,
+/// missing ones are logged and don't fail the job
+async fn build_metrics(tag: &str, crate_sizes: bool) -> Option<Measurement> {
+    let info: build::BuildInfo = match read_image_json(tag, "/build.json").await {
+        Ok(info) => info,
+        Err(e) => {
+            warn!(error = %e, "no build metrics");
+            return None;
+        }
+    };
+    let bloat: Option<build::Bloat> = if crate_sizes {
+        match read_image_json(tag, "/bloat.json").await {
+            Ok(bloat) => Some(bloat),
+            Err(e) => {
+                warn!(error = %e, "no crate sizes");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    Some(build::measurement(&info, bloat.as_ref()))
+}
+
+/// Samples the resource usage of a running container every `interval`
+/// until `stop` is closed
+async fn sample(container: String, interval: Duration, stop: Receiver<()>) -> Vec<Sample> {
+    let mut samples = Vec::new();
+    while async_std::future::timeout(interval, stop.recv())
+        .await
+        .is_err()
+    {
+        let args = ["stats", "--no-stream", "--format", "{{json .}}", &container];
+        match docker(&args).await {
+            Ok(out) => samples.extend(resources::parse_stats(
+                &String::from_utf8_lossy(&out.stdout),
+                time::now(),
+            )),
+            // the container may not have started yet or already be gone
+            Err(e) => debug!(error = %e, "no resource sample"),
+        }
+    }
+    samples
+}
+
+/// Runs a phase of a job, giving up when it takes longer than `limit`
+/// seconds or the job is cancelled
+async fn within<T, F>(phase: Phase, limit: u64, cancel: &Receiver<()>, f: F) -> Result<T, Error>
+where
+    F: Future<Output = Result<T, Error>>,
+{
+    let limited = async {
+        if limit == 0 {
+            return f.await;
+        }
+        async_std::future::timeout(Duration::from_secs(limit), f)
+            .await
+            .unwrap_or_else(|_| {
+                Err(Error::Runner(format!(
+                    "{} timed out after {}s",
+                    phase.name(),
+                    limit
+                )))
+            })
+    };
+    let cancelled = async {
+        let _ = cancel.recv().await;
+        Err(Error::Runner(format!(
+            "job cancelled during {}",
+            phase.name()
+        )))
+    };
+    limited.race(cancelled).await
+}
+
+async fn get_report(
+    hash: &str,
+    opts: &ServeOpts,
+    metrics: &Metrics,
+    cancel: &Receiver<()>,
+) -> Result<Report, Error> {
+    let crate_sizes = opts.crate_sizes;
     // calculate short commit hash
-    let short_commit_hash = &hash[..6];
+    let short_commit_hash = hash.get(..6).unwrap_or(hash);
 
     let tag = format!("tremor-benchmark:{}", short_commit_hash);
 
     // add the commit hash as tag
-    Command::new("docker")
-        .args(&[
-            "build",
-            "-t",
-            &tag,
-            "-f",
-            "Dockerfile.bench",
-            "--build-arg",
-            &format!("commithash={}", hash),
-            "docker",
-        ])
-        .output()
-        .await?;
+    let start = Instant::now();
+    info!("building image");
+    let commithash = format!("commithash={}", hash);
+    let bloat = format!("bloat={}", crate_sizes);
+    let build = [
+        "build",
+        "-t",
+        &tag,
+        "-f",
+        "Dockerfile.bench",
+        "--build-arg",
+        &commithash,
+        "--build-arg",
+        &bloat,
+        "docker",
+    ];
+    // reading the build metrics starts containers as well, so it is part of
+    // the phase that can time out or be cancelled
+    let built = within(Phase::Build, opts.build_timeout, cancel, async {
+        docker(&build).await?;
+        Ok(build_metrics(&tag, crate_sizes).await)
+    })
+    .instrument(info_span!("phase", phase = "build"))
+    .await;
+    // failed and timed out phases are recorded as well
+    metrics.phase(Phase::Build, start.elapsed());
+    let build = match built {
+        Ok(build) => build,
+        Err(e) => {
+            // the image exists when giving up on its build metrics
+            if let Err(e) = docker(&["image", "rm", &tag]).await {
+                debug!(error = %e, "failed to remove image");
+            }
+            return Err(e);
+        }
+    };
 
     // run benchmarks inside docker image and store it in a report.json
-    let r = Command::new("docker").args(["run", &tag]).output().await?;
-    Command::new("docker")
-        .args(&["image", "rm", &tag])
-        .output()
-        .await?;
-    convert_into_relevant_data(serde_json::from_slice(&r.stdout)?, hash)
+    let start = Instant::now();
+    info!("running benchmarks");
+    let container = format!("tremor-benchmark-{}", short_commit_hash);
+    let (stop, stopped) = bounded(1);
+    let sampler = (opts.sample_interval > 0).then(|| {
+        let interval = Duration::from_secs(opts.sample_interval);
+        task::spawn(sample(container.clone(), interval, stopped))
+    });
+    let run = ["run", "--rm", "--name", &container, &tag];
+    let r = within(Phase::Run, opts.run_timeout, cancel, docker(&run))
+        .instrument(info_span!("phase", phase = "run"))
+        .await;
+    metrics.phase(Phase::Run, start.elapsed());
+    if r.is_err() {
+        // killing the client leaves the container running, otherwise
+        // it is already gone
+        if let Err(e) = docker(&["rm", "--force", &container]).await {
+            debug!(error = %e, "failed to remove container");
+        }
+    }
+    drop(stop);
+    let samples = match sampler {
+        Some(sampler) => sampler.await,
+        None => Vec::new(),
+    };
+    if let Err(e) = docker(&["image", "rm", &tag]).await {
+        warn!(error = %e, "failed to remove image");
+    }
+    let r = r?;
+
+    let start = Instant::now();
+    // a report of our own runner that doesn't parse is no client error
+    let report = info_span!("phase", phase = "parse")
+        .in_scope(|| report::parse(&r.stdout, None, None))
+        .map_err(|e| match e {
+            Error::Validation(msg) => Error::Parse(msg),
+            e => e,
+        });
+    metrics.phase(Phase::Parse, start.elapsed());
+    report.map(|mut report| {
+        report.measurements.extend(build);
+        report.measurements.extend(resources::measurement(&samples));
+        report.samples = samples;
+        report
+    })
+}
+
+/// Runs the benchmarks of a job and stores them, the run is recorded as
+/// failed if they can't be run.
+async fn run_job(state: &State, job: &Job, cancel: &Receiver<()>) -> Result<(), Error> {
+    let sha = job.commit_hash.clone();
+    let job_id = job.id as i64;
+    let source = job.source;
+    let machine = state.opts.machine.clone();
+    let run_id = db::run(&state.pool, move |c| {
+        db::start_run(c, &sha, Some(job_id), source, machine.as_deref())
+    })
+    .await?;
+
+    let (report, res) =
+        match get_report(&job.commit_hash, &state.opts, &state.metrics, cancel).await {
+            Ok(r) => (Some(r), Ok(())),
+            Err(e) => (None, Err(e)),
+        };
+    let rows = report.as_ref().map_or(0, |r| r.measurements.len());
+    db::run(&state.pool, move |c| {
+        db::finish_run(c, run_id, report.as_ref())
+    })
+    .instrument(info_span!("phase", phase = "store", rows))
+    .await?;
+    res
+}
+
+/// Why backfill jobs are cancelled when other jobs are queued
+const PREEMPTED: &str = "preempted by a job of higher priority";
+
+/// Queues a job for the worker, stopping a running backfill so it doesn't
+/// hold up the new job
+async fn queue_job(
+    state: &State,
+    commit: &str,
+    source: Source,
+    author: Option<&str>,
+) -> Result<Job, Error> {
+    let limit = state.opts.max_pr_jobs;
+    let job = if source == Source::PullRequest && limit > 0 {
+        state.jobs.try_enqueue(commit, source, author, limit)?
+    } else {
+        state.jobs.enqueue(commit, source, author)
+    };
+    info!(
+        job_id = job.id,
+        commit,
+        source = source.as_str(),
+        "job queued"
+    );
+    for id in state.jobs.running(Source::Backfill) {
+        info!(job_id = id, "preempting backfill");
+        state.jobs.cancel(id, PREEMPTED);
+    }
+    state.tx.send(job.clone()).await?;
+    Ok(job)
+}
+
+/// Queues the job of `POST /jobs`, signed like the webhook
+async fn create_job(state: &State, req: Request<Body>) -> Result<Response<Body>, Error> {
+    let sig = req
+        .headers()
+        .get("X-Hub-Signature-256")
+        .and_then(|v| v.to_str().ok())
+        .map(ToString::to_string);
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    verify_signature(state, sig.as_deref(), &body)?;
+    let new: NewJob = serde_json::from_slice(&body)?;
+    validate_commit(&new.commit)?;
+    match (new.source, &new.author) {
+        (Source::Manual, _) | (Source::PullRequest, Some(_)) => {}
+        (Source::PullRequest, None) => {
+            return Err(Error::Validation(
+                "pull request jobs need an `author`".to_string(),
+            ))
+        }
+        (source, _) => {
+            return Err(Error::Validation(format!(
+                "jobs can't be queued as `{}`",
+                source.as_str()
+            )))
+        }
+    }
+    let job = queue_job(state, &new.commit, new.source, new.author.as_deref()).await?;
+    json_response(StatusCode::CREATED, &job)
+}
+
+/// A job for the most recently skipped commit a backfill wasn't recently
+/// tried on
+async fn backfill_job(state: &State) -> Option<Job> {
+    let retry = chrono::Duration::seconds(state.opts.backfill_retry as i64);
+    let retry_before = time::now() - retry;
+    let commit = match db::run(&state.pool, move |c| db::backfill(c, retry_before)).await {
+        Ok(commit) => commit?,
+        Err(e) => {
+            error!(error = %e, "failed to look up skipped commits");
+            return None;
+        }
+    };
+    let job = state
+        .jobs
+        .enqueue(&commit.commit_sha, Source::Backfill, None);
+    info!(job_id = job.id, commit = %job.commit_hash, "backfill queued");
+    Some(job)
+}
+
+/// How long until a failed backfill is due to be retried, `None` if there
+/// is none
+async fn backfill_due(state: &State) -> Option<Duration> {
+    let tried = match db::run(&state.pool, db::first_backfill).await {
+        Ok(tried) => tried?,
+        Err(e) => {
+            error!(error = %e, "failed to look up skipped commits");
+            return None;
+        }
+    };
+    let due = tried + chrono::Duration::seconds(state.opts.backfill_retry as i64);
+    Some((due - time::now()).to_std().unwrap_or_default())
+}
+
+/// Records the jobs the queue skipped in favour of `newest`
+async fn skip_jobs(state: &State, newest: &Job, skipped: Vec<Job>) {
+    for job in &skipped {
+        info!(job_id = job.id, commit = %job.commit_hash, superseded_by = %newest.commit_hash, "job skipped");
+        state.jobs.skip(job.id, &newest.commit_hash);
+        state.metrics.job_finished(JobStatus::Skipped);
+    }
+    let shas: Vec<String> = skipped.into_iter().map(|j| j.commit_hash).collect();
+    let newest = newest.commit_hash.clone();
+    if let Err(e) = db::run(&state.pool, move |c| db::record_skipped(c, &shas, &newest)).await {
+        error!(error = %e, "failed to record skipped commits");
+    }
+}
+
+/// Records how a job went
+fn finish_job(state: &State, job: &Job, res: Result<(), Error>) {
+    let status = state
+        .jobs
+        .finish(job.id, res.as_ref().err().map(ToString::to_string));
+    match (status, res) {
+        (JobStatus::Cancelled, _) => info!("job cancelled"),
+        (_, Ok(())) => info!("job finished"),
+        (_, Err(e)) => error!(error = %e, "job failed"),
+    }
+    state.metrics.job_finished(status);
+}
+
+/// Checks the `X-Hub-Signature-256` of a body when a key is configured
+fn verify_signature(state: &State, sig: Option<&str>, body: &[u8]) -> Result<(), Error> {
+    if let Some(key) = &state.opts.key {
+        if sig != Some(sign(key, body)?.as_str()) {
+            return Err(Error::Auth("bad hmac".to_string()));
+        };
+    };
+    Ok(())
+}
+
+/// Checks the number of runs a route was asked for
+fn validate_last(last: i64) -> Result<(), Error> {
+    if !(1..=MAX_LAST).contains(&last) {
+        return Err(Error::Validation(format!(
+            "`last` must be between 1 and {}",
+            MAX_LAST
+        )));
+    }
+    Ok(())
+}
+
+fn validate_commit(sha: &str) -> Result<(), Error> {
+    if sha.is_empty() || !sha.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::Validation(format!("invalid commit `{}`", sha)));
+    }
+    Ok(())
+}
+
+/// The `X-Hub-Signature-256` of a body
+fn sign(key: &str, body: &[u8]) -> Result<String, Error> {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes())?;
+    mac.update(body);
+    Ok(format!(
+        "sha256={}",
+        base16::encode_lower(&mac.finalize().into_bytes())
+    ))
+}
+
+/// Cancels the job of `DELETE /jobs/{id}`, signed like the webhook
+async fn cancel_job(state: &State, req: Request<Body>) -> Result<Response<Body>, Error> {
+    let id = &req.uri().path()["/jobs/".len()..];
+    let id: u64 = id
+        .parse()
+        .map_err(|_| Error::Validation(format!("invalid job id `{}`", id)))?;
+    let sig = req
+        .headers()
+        .get("X-Hub-Signature-256")
+        .and_then(|v| v.to_str().ok())
+        .map(ToString::to_string);
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    verify_signature(state, sig.as_deref(), &body)?;
+    let reason = if body.is_empty() {
+        None
+    } else {
+        serde_json::from_slice::<CancelBody>(&body)?.reason
+    };
+    let reason = reason.unwrap_or_else(|| "cancelled".to_string());
+
+    let (before, job) = state
+        .jobs
+        .cancel(id, &reason)
+        .ok_or_else(|| Error::NotFound(format!("no job {}", id)))?;
+    let status = match before {
+        JobStatus::Queued => {
+            info!(job_id = id, %reason, "queued job cancelled");
+            state.metrics.job_finished(JobStatus::Cancelled);
+            StatusCode::OK
+        }
+        // the worker records the end of the job once it stopped
+        JobStatus::Running => {
+            info!(job_id = id, %reason, "stopping running job");
+            StatusCode::ACCEPTED
+        }
+        done => {
+            return Err(Error::Validation(format!(
+                "job {} is already {}",
+                id,
+                done.as_str()
+            )))
+        }
+    };
+    json_response(status, &job)
+}
+
+/// Stores a report posted to `/reports`, signed like the webhook
+async fn ingest_report(state: &State, req: Request<Body>) -> Result<Response<Body>, Error> {
+    let query: IngestQuery = serde_urlencoded::from_str(req.uri().query().unwrap_or(""))?;
+    validate_commit(&query.commit)?;
+    let sig = req
+        .headers()
+        .get("X-Hub-Signature-256")
+        .and_then(|v| v.to_str().ok())
+        .map(ToString::to_string);
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+    verify_signature(state, sig.as_deref(), &body)?;
+
+    let report = report::parse(&body, query.format, query.name.as_deref())?;
+    let results = report.measurements.len();
+    let run_id = db::run(&state.pool, move |c| {
+        db::ingest(c, &query.commit, query.machine.as_deref(), &report)
+    })
+    .await?;
+    info!(run_id, results, "report ingested");
+    let res = serde_json::json!({ "run_id": run_id, "results": results });
+    json_response(StatusCode::CREATED, &res)
 }
 
 /// This is our service handler. It receives a Request, routes on its
 /// path, and returns a Future of a Response.
-async fn run(
-    opts: Arc<Opts>,
-    tx: Sender<String>,
-    req: Request<Body>,
-) -> Result<Response<Body>, Error> {
+async fn run(state: Arc<State>, req: Request<Body>) -> Result<Response<Body>, Error> {
     match (req.method(), req.uri().path()) {
+        (&Method::GET, "/jobs") => json_response(StatusCode::OK, &state.jobs.list()),
+        (&Method::POST, "/jobs") => create_job(&state, req).await,
+        (&Method::DELETE, path) if path.starts_with("/jobs/") => cancel_job(&state, req).await,
         (&Method::GET, "/bench") => {
-            // FIXME: this is terrible
-            let connection = establish_connection();
-            let mut res: Vec<Benchmark> = benchmarks
-                // .filter(eps.gt(0.0))
-                .order(created_at.desc())
-                .limit(100)
-                .load(&connection)?;
-            res.reverse();
-            let res = serde_json::to_string(&res)?;
-            Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, "application/json")
-                .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-                .body(Body::from(res))
-                .map_err(|_| Error::Other("response error"))
+            let res = db::run(&state.pool, |c| db::recent(c, 100)).await?;
+            json_response(StatusCode::OK, &res)
         }
         // Simply echo the body back to the client.
         (&Method::POST, "/bench") => {
-            //
-            let sig = req
-                .headers()
-                .get("X-Hub-Signature-256")
-                .and_then(|v| v.to_str().ok())
-                .map(ToString::to_string);
-
             let event = req
                 .headers()
                 .get("X-GitHub-Event")
                 .and_then(|v| v.to_str().ok())
-                .map(ToString::to_string)
-                .ok_or_else(|| Error::BadRequest("missing X-GitHub-Event header".into()))?;
-
-            if event != "push" {
-                return Err(Error::BadRequest(format!(
-                    "Only runs on `push` but got {}",
-                    event
-                )));
+                .unwrap_or("unknown")
+                .to_string();
+            let delivery = req
+                .headers()
+                .get("X-GitHub-Delivery")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("unknown")
+                .to_string();
+            let span = info_span!("webhook", %delivery, %event);
+            let res = webhook(&state, req).instrument(span.clone()).await;
+            let result = match &res {
+                Ok((result, _)) => result,
+                Err(Error::Auth(_)) => "forbidden",
+                Err(e) if e.status().is_client_error() => "bad_request",
+                Err(_) => "error",
             };
+            span.in_scope(|| info!(result, "webhook handled"));
+            state.metrics.webhook(&event, result);
+            res.map(|(_, response)| response)
+        }
+        (&Method::GET, path) if path.starts_with("/commits/") => {
+            let sha = path.trim_start_matches("/commits/").to_string();
+            validate_commit(&sha)?;
+            let s = sha.clone();
+            let res = db::run(&state.pool, move |c| db::commit_details(c, &s))
+                .await?
+                .ok_or_else(|| Error::NotFound(format!("unknown commit `{}`", sha)))?;
+            json_response(StatusCode::OK, &res)
+        }
+        (&Method::POST, "/reports") => ingest_report(&state, req).await,
+        (&Method::GET, "/runs") => {
+            let query: RunsQuery = serde_urlencoded::from_str(req.uri().query().unwrap_or(""))?;
+            validate_last(query.last)?;
+            let res = db::run(&state.pool, move |c| db::recent_runs(c, query.last)).await?;
+            json_response(StatusCode::OK, &res)
+        }
+        (&Method::GET, path) if path.starts_with("/runs/") && path.ends_with("/samples") => {
+            let id = path_run_id(path)?;
+            let res = match db::run(&state.pool, move |c| db::samples(c, id)).await? {
+                Some(samples) => samples,
+                None => return Err(Error::NotFound(format!("no run {}", id))),
+            };
+            json_response(StatusCode::OK, &res)
+        }
+        (&Method::GET, "/skipped") => {
+            let query: RunsQuery = serde_urlencoded::from_str(req.uri().query().unwrap_or(""))?;
+            validate_last(query.last)?;
+            let res = db::run(&state.pool, move |c| db::skipped(c, query.last)).await?;
+            json_response(StatusCode::OK, &res)
+        }
+        (&Method::GET, "/failures") => {
+            let query: FailuresQuery = serde_urlencoded::from_str(req.uri().query().unwrap_or(""))?;
+            validate_last(query.last)?;
+            let res = db::run(&state.pool, move |c| {
+                db::failures(c, query.bench.as_deref(), query.last)
+            })
+            .await?;
+            json_response(StatusCode::OK, &res)
+        }
+        (&Method::GET, "/metrics") => {
+            let latest = db::run(&state.pool, db::latest_per_bench).await?;
+            let res = state.metrics.render(&state.jobs, &latest);
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                .body(Body::from(res))
+                .map_err(Error::from)
+        }
 
-            let body = hyper::body::to_bytes(req.into_body()).await?;
+        (&Method::GET, path) if path.starts_with("/charts/") && path.ends_with(".svg") => {
+            let name = path_bench_name(path, "/charts/")?;
+            let query: ChartQuery = serde_urlencoded::from_str(req.uri().query().unwrap_or(""))?;
+            validate_last(query.last)?;
+            // the metric is part of the cache key and the ETag
+            query.metric.validate().map_err(Error::Validation)?;
 
-            if let Some(key) = &opts.key {
-                let mut mac = HmacSha256::new_from_slice(key.as_bytes())?;
-                mac.update(&body);
-                let result = format!(
-                    "sha256={}",
-                    base16::encode_lower(&mac.finalize().into_bytes())
-                );
-                if sig != Some(result) {
-                    //FIXME: Error
-                    let mut error = Response::new(Body::from("bad hmac"));
-                    *error.status_mut() = StatusCode::FORBIDDEN;
-                    return Ok(error);
-                };
+            let n = name.clone();
+            let latest = db::run(&state.pool, move |c| db::latest(c, &n, None)).await?;
+            let latest = match latest {
+                Some(latest) => latest.commit_hash,
+                None => return Err(Error::NotFound(format!("no runs of `{}`", name))),
             };
+            let etag = format!(r#""{}-{}-{}""#, latest, query.metric.name(), query.last);
+            if not_modified(&req, &etag) {
+                return svg_response(StatusCode::NOT_MODIFIED, etag, Body::empty());
+            }
 
-            let body = serde_json::from_slice::<Value>(&body)?;
-
-            let ghref = body
-                .get("ref")
-                .and_then(Value::as_str)
-                .ok_or_else(|| Error::BadRequest("`ref` is missing".into()))?
-                .to_string();
+            let svg = if let Some(svg) = state.charts.get(&name, &query.metric, query.last, &latest)
+            {
+                svg
+            } else {
+                let (n, last) = (name.clone(), query.last);
+                let runs = db::run(&state.pool, move |c| db::history(c, &n, last)).await?;
+                let svg = chart::render(&name, &query.metric, &runs);
+                state
+                    .charts
+                    .insert(&name, &query.metric, query.last, &latest, svg.clone());
+                svg
+            };
+            svg_response(StatusCode::OK, etag, Body::from(svg))
+        }
+        (&Method::GET, path) if path.starts_with("/badge/") && path.ends_with(".svg") => {
+            let name = path_bench_name(path, "/badge/")?;
+            let query: BadgeQuery = serde_urlencoded::from_str(req.uri().query().unwrap_or(""))?;
+            query.metric.validate().map_err(Error::Validation)?;
 
-            if ghref != "refs/heads/main" {
-                return Ok(Response::new(Body::from(format!(
-                    r#"{{"branch": "{}"}}"#,
-                    ghref
-                ))));
+            let n = name.clone();
+            let latest = db::run(&state.pool, move |c| db::latest(c, &n, None)).await?;
+            let latest = match latest {
+                Some(latest) => latest,
+                None => return Err(Error::NotFound(format!("no runs of `{}`", name))),
+            };
+            let (n, commit) = (name.clone(), latest.commit_hash.clone());
+            let previous = db::run(&state.pool, move |c| db::previous(c, &n, &commit)).await?;
+            // the colour depends on the previous commit, which changes when
+            // an older one is ingested or backfilled
+            let etag = format!(
+                r#""{}-{}-{}""#,
+                latest.commit_hash,
+                previous.as_ref().map_or("none", |p| p.commit_hash.as_str()),
+                query.metric.name()
+            );
+            if not_modified(&req, &etag) {
+                return svg_response(StatusCode::NOT_MODIFIED, etag, Body::empty());
             }
 
-            let hash = body
-                .get("after")
-                .and_then(Value::as_str)
-                .ok_or_else(|| Error::BadRequest("`after` is missing".into()))?
-                .to_string();
+            let measured = query.metric.get(&latest).ok_or_else(|| {
+                Error::NotFound(format!(
+                    "no `{}` metric in the latest run of `{}`",
+                    query.metric.name(),
+                    name
+                ))
+            })?;
+            let value = format!("{:.1} {}", measured.value, measured.unit);
+            let color = badge::color(&query.metric, &latest, previous.as_ref());
+            let svg = badge::render(&name, &value, color);
+            svg_response(StatusCode::OK, etag, Body::from(svg))
+        }
+        (&Method::GET, path)
+            if path.starts_with("/hist/")
+                && (path.ends_with(".svg") || path.ends_with(".json")) =>
+        {
+            let json = path.ends_with(".json");
+            let name = path_bench_name(path, "/hist/")?;
+            let query: HistQuery = serde_urlencoded::from_str(req.uri().query().unwrap_or(""))?;
+            // the prefixes end up in a LIKE pattern
+            for commit in query.commit.iter().chain(&query.compare) {
+                validate_commit(commit)?;
+            }
 
-            tx.send(hash.clone()).await?;
+            let mut series = Vec::with_capacity(2);
+            let commits =
+                std::iter::once(query.commit.clone()).chain(query.compare.clone().map(Some));
+            for commit in commits {
+                let n = name.clone();
+                let run =
+                    db::run(&state.pool, move |c| db::latest(c, &n, commit.as_deref())).await?;
+                match run {
+                    Some(run) => series.push(hist::Series::new(run.commit_hash, &run.hist)),
+                    None => return Err(Error::NotFound(format!("no runs of `{}`", name))),
+                }
+            }
 
-            Ok(Response::new(Body::from(format!(
-                r#"{{"hash": "{}"}}"#,
-                hash
-            ))))
+            if json {
+                return json_response(StatusCode::OK, &series);
+            }
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "image/svg+xml")
+                .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                .body(Body::from(hist::render(&name, &series)))
+                .map_err(Error::from)
+        }
+        (&Method::GET, "/export") => {
+            let query: ExportQuery = serde_urlencoded::from_str(req.uri().query().unwrap_or(""))?;
+            let cursor = export::Cursor::new(query.filter, query.format)?;
+            let pool = state.pool.clone();
+            let batches = stream::unfold(Some(cursor), move |cursor| {
+                let pool = pool.clone();
+                async move {
+                    let mut cursor = cursor?;
+                    let res = db::run(&pool, move |c| {
+                        cursor.next_batch(c).map(|batch| batch.map(|b| (b, cursor)))
+                    })
+                    .await;
+                    match res {
+                        Ok(Some((batch, cursor))) => Some((Ok(batch), Some(cursor))),
+                        Ok(None) => None,
+                        Err(e) => Some((Err(e), None)),
+                    }
+                }
+            });
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, query.format.content_type())
+                .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                .body(Body::wrap_stream(batches))
+                .map_err(Error::from)
+        }
+        (&Method::GET, path) if dashboard::asset(path).is_some() => {
+            let (content_type, asset) = dashboard::asset(path).ok_or("unknown asset")?;
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(asset))
+                .map_err(Error::from)
         }
 
         // Return the 404 Not Found for other routes.
-        _ => {
-            let mut error = Response::new(Body::from("not found"));
-            *error.status_mut() = StatusCode::NOT_FOUND;
-            Ok(error)
-        }
+        _ => Err(Error::NotFound("not found".to_string())),
+    }
+}
+
+/// Handles a GitHub webhook delivery, returns the result label recorded in
+/// the metrics along with the response.
+async fn webhook(
+    state: &State,
+    req: Request<Body>,
+) -> Result<(&'static str, Response<Body>), Error> {
+    let sig = req
+        .headers()
+        .get("X-Hub-Signature-256")
+        .and_then(|v| v.to_str().ok())
+        .map(ToString::to_string);
+
+    let event = req
+        .headers()
+        .get("X-GitHub-Event")
+        .and_then(|v| v.to_str().ok())
+        .map(ToString::to_string)
+        .ok_or_else(|| Error::Validation("missing X-GitHub-Event header".into()))?;
+
+    if event != "push" {
+        return Err(Error::Validation(format!(
+            "Only runs on `push` but got {}",
+            event
+        )));
+    };
+
+    let body = hyper::body::to_bytes(req.into_body()).await?;
+
+    verify_signature(state, sig.as_deref(), &body)?;
+
+    let body = serde_json::from_slice::<github::PushEvent>(&body)?;
+
+    let ghref = body
+        .git_ref
+        .clone()
+        .ok_or_else(|| Error::Validation("`ref` is missing".into()))?;
+
+    if ghref != "refs/heads/main" {
+        return Ok((
+            "ignored",
+            Response::new(Body::from(format!(r#"{{"branch": "{}"}}"#, ghref))),
+        ));
     }
+
+    let hash = body
+        .after
+        .clone()
+        .ok_or_else(|| Error::Validation("`after` is missing".into()))?;
+
+    let after = hash.clone();
+    let push_id = db::run(&state.pool, move |c| {
+        let received_at = time::now();
+        let branch = ghref.trim_start_matches("refs/heads/");
+        let pusher = body.pusher();
+        let push = NewPush {
+            branch,
+            before_sha: body.before.as_deref().unwrap_or_default(),
+            after_sha: &after,
+            compare_url: body.compare.as_deref(),
+            pusher: pusher.as_deref(),
+            received_at,
+        };
+        db::record_push(c, &push, body.commit_infos(branch, received_at))
+    })
+    .await?;
+    info!(push_id, "push recorded");
+
+    queue_job(state, &hash, Source::Main, None).await?;
+
+    Ok((
+        "queued",
+        Response::new(Body::from(format!(r#"{{"hash": "{}"}}"#, hash))),
+    ))
 }
 
-fn establish_connection() -> SqliteConnection {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    SqliteConnection::establish(&database_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+/// Extracts the benchmark name from paths like `/charts/{bench_name}.svg`
+fn path_bench_name(path: &str, prefix: &str) -> Result<String, Error> {
+    let name = &path[prefix.len()..path.rfind('.').unwrap_or(path.len())];
+    percent_decode_str(name)
+        .decode_utf8()
+        .map(|name| name.to_string())
+        .map_err(|_| Error::Validation("invalid benchmark name".into()))
+}
+
+/// Extracts the run id from `/runs/{id}/samples`
+fn path_run_id(path: &str) -> Result<i64, Error> {
+    let id = path
+        .strip_prefix("/runs/")
+        .and_then(|p| p.strip_suffix("/samples"))
+        .unwrap_or_default();
+    id.parse()
+        .map_err(|_| Error::Validation(format!("invalid run id `{}`", id)))
+}
+
+fn not_modified(req: &Request<Body>, etag: &str) -> bool {
+    req.headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        == Some(etag)
+}
+
+/// A JSON response that can be fetched from any origin
+fn json_response<T: Serialize>(status: StatusCode, body: &T) -> Result<Response<Body>, Error> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(Body::from(serde_json::to_string(body)?))
+        .map_err(Error::from)
+}
+
+fn svg_response(status: StatusCode, etag: String, body: Body) -> Result<Response<Body>, Error> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "image/svg+xml")
+        .header(header::CACHE_CONTROL, "public, max-age=300")
+        .header(header::ETAG, etag)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(body)
+        .map_err(Error::from)
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     dotenv::dotenv().ok();
 
-    let (tx, rx) = bounded::<String>(64);
+    let (args, deprecated) = with_default_subcommand(std::env::args().collect());
+    let opts: Opts = Opts::parse_from(args);
+    init_tracing(opts.log_format);
+    if deprecated {
+        warn!("running without a subcommand is deprecated, use `tremor-benchmark serve [key]`");
+    }
+    match opts.command {
+        SubCommand::Serve(opts) => serve(opts).await,
+        SubCommand::Export(opts) => export(opts),
+        SubCommand::Import(opts) => import(opts),
+        SubCommand::Migrate(opts) => migrate(opts),
+        SubCommand::Ingest(opts) => ingest(opts),
+        SubCommand::Cancel(opts) => cancel(opts).await,
+    }
+}
+
+fn init_tracing(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    // logs go to stderr so they don't mix with exported data on stdout
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+fn import(opts: ImportOpts) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let format = opts.format.unwrap_or(if opts.input.ends_with(".csv") {
+        export::Format::Csv
+    } else {
+        export::Format::Jsonl
+    });
+    let input: Box<dyn Read> = if opts.input == "-" {
+        Box::new(std::io::stdin())
+    } else {
+        Box::new(File::open(&opts.input)?)
+    };
+    let connection = db::pool(&db::database_url()?)?.get()?;
+    let summary = import::import(&connection, input, format)?;
+    println!(
+        "inserted: {}, skipped: {}, invalid: {}",
+        summary.inserted, summary.skipped, summary.invalid
+    );
+    Ok(())
+}
+
+fn ingest(opts: IngestOpts) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    validate_commit(&opts.commit)?;
+    let mut data = Vec::new();
+    if opts.input == "-" {
+        std::io::stdin().read_to_end(&mut data)?;
+    } else {
+        File::open(&opts.input)?.read_to_end(&mut data)?;
+    }
+    let report = report::parse(&data, opts.format, opts.name.as_deref())?;
+    let connection = db::pool(&db::database_url()?)?.get()?;
+    let run_id = db::ingest(&connection, &opts.commit, opts.machine.as_deref(), &report)?;
+    println!("run: {}, results: {}", run_id, report.measurements.len());
+    Ok(())
+}
+
+async fn cancel(opts: CancelOpts) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let body = serde_json::to_vec(&CancelBody {
+        reason: Some(opts.reason),
+    })?;
+    let mut req = Request::builder()
+        .method(Method::DELETE)
+        .uri(format!(
+            "{}/jobs/{}",
+            opts.url.trim_end_matches('/'),
+            opts.id
+        ))
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(key) = &opts.key {
+        req = req.header("X-Hub-Signature-256", sign(key, &body)?);
+    }
+    let res = hyper::Client::new()
+        .request(req.body(Body::from(body))?)
+        .await?;
+    let status = res.status();
+    let body = hyper::body::to_bytes(res.into_body()).await?;
+    let body = String::from_utf8_lossy(&body);
+    if !status.is_success() {
+        return Err(format!("{}: {}", status, body).into());
+    }
+    println!("{}", body);
+    Ok(())
+}
+
+fn migrate(opts: MigrateOpts) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let connection = db::pool(&db::database_url()?)?.get()?;
+    match opts.command {
+        MigrateCommand::Status => {
+            for m in migrations::status(&connection)? {
+                let state = if m.applied { "applied" } else { "pending" };
+                println!("{:8} {}_{}", state, m.version, m.name);
+            }
+        }
+        MigrateCommand::Run => {
+            for m in migrations::run(&connection)? {
+                println!("applied {}_{}", m.version, m.name);
+            }
+        }
+    }
+    Ok(())
+}
 
+fn export(opts: ExportOpts) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut out: Box<dyn Write> = match &opts.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout())),
+    };
+    let filter = export::Filter {
+        bench: opts.bench,
+        commit: opts.commit,
+    };
+    let connection = db::pool(&db::database_url()?)?.get()?;
+    let mut cursor = export::Cursor::new(filter, opts.format)?;
+    while let Some(batch) = cursor.next_batch(&connection)? {
+        out.write_all(&batch)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+async fn serve(opts: ServeOpts) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let pool = db::pool(&db::database_url()?)?;
+    if opts.no_migrate {
+        info!("skipping database migrations");
+    } else {
+        for m in migrations::run(&pool.get()?)? {
+            info!(version = m.version, name = m.name, "applied migration");
+        }
+    }
+    let (tx, rx) = bounded::<Job>(64);
+    let state = Arc::new(State {
+        opts,
+        jobs: Jobs::default(),
+        charts: ChartCache::default(),
+        metrics: Metrics::default(),
+        pool,
+        tx,
+        requests: AtomicU64::new(0),
+    });
+
+    let worker_state = state.clone();
     task::spawn(async move {
-        let connection = establish_connection();
-        while let Ok(hash) = rx.recv().await {
-            match get_report(&hash).await {
-                Err(e) => eprint!("Report Error {}", e),
-                Ok(r) => {
-                    println!("data: {:?}", r);
-                    for b in r {
-                        diesel::insert_into(benchmarks::table)
-                            .values(&b.as_new())
-                            .execute(&connection)
-                            .expect("Error saving new post");
+        let opts = &worker_state.opts;
+        // jobs the queue decided to run
+        let mut ready = VecDeque::new();
+        // ids of the ready jobs that were sampled and can't be skipped anymore
+        let mut sampled = HashSet::new();
+        loop {
+            let mut waiting: Vec<Job> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+            if ready.is_empty() && waiting.is_empty() {
+                let backfill = if opts.backfill {
+                    backfill_job(&worker_state).await
+                } else {
+                    None
+                };
+                let job = match backfill {
+                    Some(job) => Some(Ok(job)),
+                    None => {
+                        let due = if opts.backfill {
+                            backfill_due(&worker_state).await
+                        } else {
+                            None
+                        };
+                        let retry = async {
+                            match due {
+                                Some(due) => task::sleep(due).await,
+                                None => async_std::future::pending().await,
+                            }
+                            None
+                        };
+                        async { Some(rx.recv().await) }.race(retry).await
                     }
+                };
+                match job {
+                    Some(Ok(job)) => waiting.push(job),
+                    Some(Err(_)) => break,
+                    // a failed backfill is due to be retried
+                    None => continue,
+                }
+            }
+            // cancelled jobs don't supersede anything
+            let queued =
+                |j: &Job| worker_state.jobs.get(j.id).map(|j| j.status) == Some(JobStatus::Queued);
+            waiting.retain(queued);
+            ready.retain(queued);
+            if let Some((newest, skipped)) = queue::schedule(
+                opts.queue,
+                opts.sample_skipped,
+                &mut ready,
+                &mut sampled,
+                waiting,
+            ) {
+                skip_jobs(&worker_state, &newest, skipped).await;
+            }
+            let job = match queue::next(&mut ready) {
+                Some(job) => job,
+                None => continue,
+            };
+            let span = info_span!("job", job_id = job.id, commit = %job.commit_hash);
+            let cancel = match worker_state.jobs.start(job.id) {
+                Some(cancel) => cancel,
+                None => {
+                    span.in_scope(|| info!("skipping cancelled job"));
+                    continue;
                 }
             };
+            span.in_scope(|| info!("job started"));
+            let res = run_job(&worker_state, &job, &cancel)
+                .instrument(span.clone())
+                .await;
+            span.in_scope(|| finish_job(&worker_state, &job, res));
+            let preempted = worker_state
+                .jobs
+                .get(job.id)
+                .and_then(|j| j.cancel_reason)
+                .is_some_and(|r| r == PREEMPTED);
+            if job.source == Source::Backfill && !preempted {
+                let sha = job.commit_hash;
+                if let Err(e) =
+                    db::run(&worker_state.pool, move |c| db::record_backfill(c, &sha)).await
+                {
+                    error!(error = %e, "failed to record backfill");
+                }
+            }
         }
     });
-    let opts: Opts = Opts::parse();
 
     let addr = ([0, 0, 0, 0], 8080).into();
 
     let service = make_service_fn(move |_| {
-        let o = Arc::new(opts.clone());
-        let tx = tx.clone();
+        let state = state.clone();
         async move {
             Ok::<_, hyper::Error>(service_fn(move |req| {
-                let o = o.clone();
-                let tx = tx.clone();
+                let state = state.clone();
 
+                let request_id = state.request_id(&req);
+                let span = info_span!(
+                    "request",
+                    method = %req.method(),
+                    path = %req.uri().path(),
+                    %request_id
+                );
                 async move {
-                    match run(o, tx, req).await {
-                        Ok(r) => Ok(r),
-                        Err(Error::BadRequest(e)) => {
-                            let mut error = Response::new(Body::from(e));
-                            *error.status_mut() = StatusCode::BAD_REQUEST;
-                            Ok(error)
-                        }
-                        Err(Error::Hyper(e)) => Err(e),
+                    let mut response = match run(state, req).await {
+                        Ok(r) => r,
+                        Err(Error::Hyper(e)) => return Err(e),
                         Err(e) => {
-                            let mut error = Response::new(Body::from(format!("Error: {:?}", e)));
-                            *error.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-                            Ok(error)
+                            if e.status().is_client_error() {
+                                warn!(error = %e, kind = e.kind(), "bad request");
+                            } else {
+                                error!(error = ?e, kind = e.kind(), "request failed");
+                            }
+                            e.into_response(&request_id)
                         }
+                    };
+                    if let Ok(v) = header::HeaderValue::from_str(&request_id) {
+                        response.headers_mut().insert(REQUEST_ID, v);
                     }
+                    Ok(response)
                 }
+                .instrument(span)
             }))
         }
     });
 
     let server = Server::bind(&addr).serve(service);
 
-    println!("Listening on http://{}", addr);
+    info!("Listening on http://{}", addr);
 
     server.await?;
 
@@ -279,3 +1505,80 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
 //     Ok(())
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_chart_metric() -> Result<(), Error> {
+        for (query, valid) in [("", true), ("metric=p99.9", true), ("metric=a%0Db", false)] {
+            let query: ChartQuery = serde_urlencoded::from_str(query)?;
+            assert_eq!(query.metric.validate().is_ok(), valid, "{:?}", query.metric);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_last() {
+        assert!(validate_last(1).is_ok());
+        assert!(validate_last(MAX_LAST).is_ok());
+        for last in [-1, 0, MAX_LAST + 1] {
+            let status = validate_last(last).map_err(|e| e.status());
+            assert_eq!(status, Err(StatusCode::BAD_REQUEST), "{}", last);
+        }
+    }
+
+    #[test]
+    fn test_path_run_id() {
+        assert_eq!(path_run_id("/runs/42/samples").ok(), Some(42));
+        for path in ["/runs/samples", "/runs//samples", "/runs/x/samples"] {
+            let status = path_run_id(path).map_err(|e| e.status());
+            assert_eq!(status, Err(StatusCode::BAD_REQUEST), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_backfill_retry() {
+        let retry = |arg: &str| {
+            ServeOpts::try_parse_from(["serve", "--backfill-retry", arg]).map(|o| o.backfill_retry)
+        };
+        assert_eq!(retry("60").ok(), Some(60));
+        assert!(retry("0").is_err());
+        assert!(retry("soon").is_err());
+    }
+
+    #[test]
+    fn test_default_subcommand() {
+        let args = |args: &[&str]| -> Vec<String> {
+            std::iter::once("tremor-benchmark")
+                .chain(args.iter().copied())
+                .map(ToString::to_string)
+                .collect()
+        };
+        for (given, expected) in [
+            (vec![], Some(vec!["serve"])),
+            (vec!["secret"], Some(vec!["serve", "secret"])),
+            (
+                vec!["--log-format", "json", "secret"],
+                Some(vec!["--log-format", "json", "serve", "secret"]),
+            ),
+            (
+                vec!["--log-format=json"],
+                Some(vec!["--log-format=json", "serve"]),
+            ),
+            (vec!["serve", "secret"], None),
+            (vec!["migrate", "status"], None),
+            (vec!["help"], None),
+            (vec!["--help"], None),
+        ] {
+            let (rewritten, deprecated) = with_default_subcommand(args(&given));
+            assert_eq!(deprecated, expected.is_some(), "{:?}", given);
+            assert_eq!(rewritten, args(&expected.unwrap_or(given)));
+        }
+        let (args, _) = with_default_subcommand(args(&["secret"]));
+        let opts = Opts::try_parse_from(args).map(|o| o.command);
+        assert!(matches!(opts, Ok(SubCommand::Serve(s)) if s.key.as_deref() == Some("secret")));
+    }
+}
@@ -0,0 +1,297 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Service metrics in the Prometheus text exposition format
+
+use crate::hist;
+use crate::jobs::{JobStatus, Jobs};
+use crate::model::Benchmark;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds in seconds of the job phase duration histogram buckets
+const BUCKETS: [f64; 9] = [1.0, 10.0, 30.0, 60.0, 300.0, 600.0, 1200.0, 1800.0, 3600.0];
+
+/// GitHub events that are counted by name, `ping` is sent when a webhook is
+/// set up
+const EVENTS: [&str; 2] = ["push", "ping"];
+
+/// name, help text and value of the per benchmark gauges
+type Gauge = (&'static str, &'static str, fn(&Benchmark) -> Option<f64>);
+
+const GAUGES: [Gauge; 3] = [
+    ("eps", "Throughput of the latest run in k events/s", |b| {
+        Some(f64::from(b.eps))
+    }),
+    ("mbps", "Throughput of the latest run in MB/s", |b| {
+        Some(f64::from(b.mbps))
+    }),
+    ("p99", "99th percentile latency of the latest run", |b| {
+        hist::percentile(&hist::parse(&b.hist), 0.99)
+    }),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    Build,
+    Run,
+    Parse,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Build => "build",
+            Self::Run => "run",
+            Self::Parse => "parse",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, v: f64) {
+        for (bucket, le) in self.buckets.iter_mut().zip(BUCKETS.iter()) {
+            if v <= *le {
+                *bucket += 1;
+            }
+        }
+        self.sum += v;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    jobs: BTreeMap<&'static str, u64>,
+    webhooks: BTreeMap<(&'static str, &'static str), u64>,
+    phases: BTreeMap<Phase, Histogram>,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+/// Escapes a label value as required by the exposition format
+fn label(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    fn inner(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn job_finished(&self, status: JobStatus) {
+        let result = match status {
+            JobStatus::Cancelled => "cancelled",
+            JobStatus::Skipped => "skipped",
+            JobStatus::Failed => "failure",
+            _ => "success",
+        };
+        *self.inner().jobs.entry(result).or_default() += 1;
+    }
+
+    /// Counts a webhook delivery. The event is given by the client, so
+    /// events we don't know are counted as `other`.
+    pub fn webhook(&self, event: &str, result: &'static str) {
+        let event = EVENTS
+            .iter()
+            .find(|e| **e == event)
+            .copied()
+            .unwrap_or("other");
+        *self.inner().webhooks.entry((event, result)).or_default() += 1;
+    }
+
+    pub fn phase(&self, phase: Phase, duration: Duration) {
+        self.inner()
+            .phases
+            .entry(phase)
+            .or_default()
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Renders all metrics, `latest` holds the most recent run of each
+    /// benchmark.
+    pub fn render(&self, jobs: &Jobs, latest: &[Benchmark]) -> String {
+        let inner = self.inner();
+        let mut out = String::new();
+        // writing to a String can't fail
+        let _ = writeln!(
+            out,
+            "# HELP tremor_benchmark_queue_depth Jobs waiting to be run\n\
+             # TYPE tremor_benchmark_queue_depth gauge\n\
+             tremor_benchmark_queue_depth {}",
+            jobs.count(JobStatus::Queued)
+        );
+        let _ = writeln!(
+            out,
+            "# HELP tremor_benchmark_jobs_running Jobs currently running\n\
+             # TYPE tremor_benchmark_jobs_running gauge\n\
+             tremor_benchmark_jobs_running {}",
+            jobs.count(JobStatus::Running)
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP tremor_benchmark_jobs_total Finished jobs by result\n\
+             # TYPE tremor_benchmark_jobs_total counter"
+        );
+        for result in ["success", "failure", "cancelled", "skipped"] {
+            let _ = writeln!(
+                out,
+                r#"tremor_benchmark_jobs_total{{result="{}"}} {}"#,
+                result,
+                inner.jobs.get(result).copied().unwrap_or_default()
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP tremor_benchmark_job_phase_duration_seconds Duration of the phases of a job\n\
+             # TYPE tremor_benchmark_job_phase_duration_seconds histogram"
+        );
+        for (phase, h) in &inner.phases {
+            for (le, count) in BUCKETS.iter().zip(h.buckets.iter()) {
+                let _ = writeln!(
+                    out,
+                    r#"tremor_benchmark_job_phase_duration_seconds_bucket{{phase="{}",le="{}"}} {}"#,
+                    phase.name(),
+                    le,
+                    count
+                );
+            }
+            let _ = writeln!(
+                out,
+                r#"tremor_benchmark_job_phase_duration_seconds_bucket{{phase="{p}",le="+Inf"}} {c}
+tremor_benchmark_job_phase_duration_seconds_sum{{phase="{p}"}} {s}
+tremor_benchmark_job_phase_duration_seconds_count{{phase="{p}"}} {c}"#,
+                p = phase.name(),
+                c = h.count,
+                s = h.sum
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP tremor_benchmark_webhooks_total Received webhooks by event and result\n\
+             # TYPE tremor_benchmark_webhooks_total counter"
+        );
+        for ((event, result), count) in &inner.webhooks {
+            let _ = writeln!(
+                out,
+                r#"tremor_benchmark_webhooks_total{{event="{}",result="{}"}} {}"#,
+                label(event),
+                result,
+                count
+            );
+        }
+
+        for (name, help, value) in GAUGES.iter() {
+            let _ = writeln!(
+                out,
+                "# HELP tremor_benchmark_latest_{n} {h}\n# TYPE tremor_benchmark_latest_{n} gauge",
+                n = name,
+                h = help
+            );
+            for b in latest {
+                if let Some(v) = value(b) {
+                    let _ = writeln!(
+                        out,
+                        r#"tremor_benchmark_latest_{}{{bench="{}"}} {}"#,
+                        name,
+                        label(&b.bench_name),
+                        v
+                    );
+                }
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP tremor_benchmark_latest_metric Custom metrics of the latest run\n\
+             # TYPE tremor_benchmark_latest_metric gauge"
+        );
+        for b in latest {
+            for m in &b.metrics {
+                let _ = writeln!(
+                    out,
+                    r#"tremor_benchmark_latest_metric{{bench="{}",metric="{}",unit="{}"}} {}"#,
+                    label(&b.bench_name),
+                    label(&m.name),
+                    label(&m.unit),
+                    m.value
+                );
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::Source;
+    use crate::model::{Direction, MetricValue};
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        let jobs = Jobs::default();
+        jobs.enqueue("a", Source::Main, None);
+        metrics.job_finished(JobStatus::Finished);
+        metrics.webhook("push", "queued");
+        metrics.webhook("pu\"sh", "bad_request");
+        metrics.webhook("issues", "bad_request");
+        metrics.phase(Phase::Build, Duration::from_secs(42));
+        let latest = vec![Benchmark {
+            mbps: 58.5,
+            hist: "  116735 0.99219   85280988         128.00".to_string(),
+            metrics: vec![MetricValue::new("peak_rss", 48.5, "MB", Direction::Lower)],
+            ..Benchmark::fixture("abc", 921.5)
+        }];
+
+        let out = metrics.render(&jobs, &latest);
+        assert!(out.contains("tremor_benchmark_queue_depth 1\n"));
+        assert!(out.contains(r#"tremor_benchmark_jobs_total{result="success"} 1"#));
+        assert!(out.contains(r#"tremor_benchmark_jobs_total{result="failure"} 0"#));
+        assert!(out.contains(
+            r#"tremor_benchmark_job_phase_duration_seconds_bucket{phase="build",le="30"} 0"#
+        ));
+        assert!(out.contains(
+            r#"tremor_benchmark_job_phase_duration_seconds_bucket{phase="build",le="60"} 1"#
+        ));
+        assert!(
+            out.contains(r#"tremor_benchmark_job_phase_duration_seconds_sum{phase="build"} 42"#)
+        );
+        assert!(out
+            .contains(r#"tremor_benchmark_webhooks_total{event="other",result="bad_request"} 2"#));
+        assert!(out.contains(r#"tremor_benchmark_latest_eps{bench="passthrough"} 921.5"#));
+        assert!(out.contains(r#"tremor_benchmark_latest_p99{bench="passthrough"} 116735"#));
+        assert!(out.contains(
+            r#"tremor_benchmark_latest_metric{bench="passthrough",metric="peak_rss",unit="MB"} 48.5"#
+        ));
+    }
+}
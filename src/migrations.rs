@@ -0,0 +1,285 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Schema migrations embedded into the binary. They are tracked in the same
+//! table as the diesel CLI uses, so both can be used on one database.
+//!
+//! Every migration exists once per backend, in `migrations/sqlite` and
+//! `migrations/postgres`.
+
+use crate::db::Connection;
+use crate::error::Error;
+use diesel::connection::SimpleConnection;
+use diesel::migration::{Migration, RunMigrationsError};
+use diesel_migrations::MigrationConnection;
+use serde::Serialize;
+
+/// The SQL of a migration for one backend
+pub struct Script {
+    version: &'static str,
+    up: &'static str,
+    down: &'static str,
+}
+
+pub struct Embedded {
+    /// The directory name without separators, as the diesel CLI does it
+    version: &'static str,
+    name: &'static str,
+    sqlite: Script,
+    postgres: Script,
+}
+
+macro_rules! script {
+    ($backend:literal, $version:literal, $dir:literal) => {
+        Script {
+            version: $version,
+            up: include_str!(concat!("../migrations/", $backend, "/", $dir, "/up.sql")),
+            down: include_str!(concat!("../migrations/", $backend, "/", $dir, "/down.sql")),
+        }
+    };
+}
+
+macro_rules! migration {
+    ($version:literal, $dir:literal, $name:literal) => {
+        Embedded {
+            version: $version,
+            name: $name,
+            sqlite: script!("sqlite", $version, $dir),
+            postgres: script!("postgres", $version, $dir),
+        }
+    };
+}
+
+/// All migrations, oldest first. A new directory under `migrations/` has to
+/// be added here as well.
+pub const MIGRATIONS: &[Embedded] = &[
+    migration!(
+        "20211001103631",
+        "2021-10-01-103631_create_benchmarks",
+        "create_benchmarks"
+    ),
+    migration!(
+        "20211115120000",
+        "2021-11-15-120000_normalize_schema",
+        "normalize_schema"
+    ),
+    migration!(
+        "20211120090000",
+        "2021-11-20-090000_commit_metadata",
+        "commit_metadata"
+    ),
+    migration!(
+        "20211125090000",
+        "2021-11-25-090000_timestamps",
+        "timestamps"
+    ),
+    migration!(
+        "20211128090000",
+        "2021-11-28-090000_result_status",
+        "result_status"
+    ),
+    migration!(
+        "20211201090000",
+        "2021-12-01-090000_run_metadata",
+        "run_metadata"
+    ),
+    migration!(
+        "20211205090000",
+        "2021-12-05-090000_parser_version",
+        "parser_version"
+    ),
+    migration!(
+        "20211210090000",
+        "2021-12-10-090000_result_metrics",
+        "result_metrics"
+    ),
+    migration!(
+        "20211215090000",
+        "2021-12-15-090000_run_samples",
+        "run_samples"
+    ),
+    migration!(
+        "20211220090000",
+        "2021-12-20-090000_skipped_commits",
+        "skipped_commits"
+    ),
+    migration!(
+        "20211225090000",
+        "2021-12-25-090000_run_source",
+        "run_source"
+    ),
+    migration!(
+        "20211230090000",
+        "2021-12-30-090000_backfill_attempts",
+        "backfill_attempts"
+    ),
+];
+
+impl Embedded {
+    /// The SQLite version of the migration, for tests that need to start
+    /// from an older schema
+    #[cfg(test)]
+    pub fn up_sql(&self) -> &'static str {
+        self.sqlite.up
+    }
+}
+
+impl Migration for Script {
+    fn version(&self) -> &str {
+        self.version
+    }
+
+    fn run(&self, conn: &dyn SimpleConnection) -> Result<(), RunMigrationsError> {
+        Ok(conn.batch_execute(self.up)?)
+    }
+
+    fn revert(&self, conn: &dyn SimpleConnection) -> Result<(), RunMigrationsError> {
+        Ok(conn.batch_execute(self.down)?)
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct Status {
+    pub version: &'static str,
+    pub name: &'static str,
+    pub applied: bool,
+}
+
+fn status_of<C: MigrationConnection>(connection: &C) -> Result<Vec<Status>, Error> {
+    diesel_migrations::setup_database(connection)?;
+    let applied = connection.previously_run_migration_versions()?;
+    Ok(MIGRATIONS
+        .iter()
+        .map(|m| Status {
+            version: m.version,
+            name: m.name,
+            applied: applied.contains(m.version),
+        })
+        .collect())
+}
+
+fn run_on<C: MigrationConnection>(
+    connection: &C,
+    scripts: impl Iterator<Item = &'static Script>,
+) -> Result<Vec<Status>, Error> {
+    let pending: Vec<Status> = status_of(connection)?
+        .into_iter()
+        .filter(|s| !s.applied)
+        .collect();
+    diesel_migrations::run_migrations(
+        connection,
+        scripts.map(|s| s as &dyn Migration),
+        &mut std::io::sink(),
+    )?;
+    Ok(pending)
+}
+
+/// Which of the embedded migrations have been applied
+pub fn status(connection: &Connection) -> Result<Vec<Status>, Error> {
+    match connection {
+        Connection::Sqlite(c) => status_of(&**c),
+        Connection::Postgres(c) => status_of(&**c),
+    }
+}
+
+/// Applies all pending migrations, each in its own transaction, and returns
+/// the ones that were applied.
+pub fn run(connection: &Connection) -> Result<Vec<Status>, Error> {
+    match connection {
+        Connection::Sqlite(c) => run_on(&**c, MIGRATIONS.iter().map(|m| &m.sqlite)),
+        Connection::Postgres(c) => run_on(&**c, MIGRATIONS.iter().map(|m| &m.postgres)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+    use diesel::dsl::sql;
+    use diesel::sql_types::BigInt;
+    use diesel::{Connection as _, RunQueryDsl};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_run() -> Result<(), Error> {
+        let connection = db::pool(":memory:")?.get()?;
+        assert!(status(&connection)?.iter().all(|s| !s.applied));
+        assert_eq!(run(&connection)?.len(), MIGRATIONS.len());
+        assert!(status(&connection)?.iter().all(|s| s.applied));
+        assert_eq!(run(&connection)?, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_revert_keeps_rows() -> Result<(), Error> {
+        let connection = db::pool(":memory:")?.get()?;
+        run(&connection)?;
+        let c = match &connection {
+            Connection::Sqlite(c) => c,
+            Connection::Postgres(_) => unreachable!(),
+        };
+        c.batch_execute(
+            "INSERT INTO commits (sha) VALUES ('aa00');
+            INSERT INTO benchmarks (id, name) VALUES (1, 'a');
+            INSERT INTO runs (id, commit_sha, started_at, status)
+                VALUES (1, 'aa00', '2021-12-01 00:00:00', 'finished');
+            INSERT INTO results (run_id, benchmark_id, mbps, eps, hist)
+                VALUES (1, 1, 1.0, 1.0, '');",
+        )?;
+        // reverting runs with foreign keys on, down to the normalized schema
+        for m in MIGRATIONS[2..].iter().rev() {
+            c.transaction(|| m.sqlite.revert(&**c))?;
+        }
+        let count = |table: &str| -> Result<i64, Error> {
+            Ok(
+                diesel::select(sql::<BigInt>(&format!("(SELECT count(*) FROM {})", table)))
+                    .get_result(&**c)?,
+            )
+        };
+        assert_eq!(count("runs")?, 1);
+        assert_eq!(count("results")?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_all_embedded() -> Result<(), Error> {
+        let embedded: Vec<String> = MIGRATIONS
+            .iter()
+            .map(|m| {
+                let (date, time) = m.version.split_at(8);
+                format!(
+                    "{}-{}-{}-{}_{}",
+                    &date[..4],
+                    &date[4..6],
+                    &date[6..],
+                    time,
+                    m.name
+                )
+            })
+            .collect();
+        for backend in ["sqlite", "postgres"] {
+            let mut dirs = Vec::new();
+            let path = format!("{}/migrations/{}", env!("CARGO_MANIFEST_DIR"), backend);
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    dirs.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+            dirs.sort();
+            assert_eq!(dirs, embedded);
+        }
+        Ok(())
+    }
+}
@@ -12,42 +12,555 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::schema::benchmarks;
-use serde::Serialize;
+use super::schema::{
+    commits, pushes, result_metrics, results, run_samples, runs, skipped_commits, suite_stats,
+};
+use chrono::NaiveDateTime;
+use diesel::backend::Backend;
+use diesel::Queryable;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
-#[derive(Serialize, Queryable, Debug)]
+/// A stored benchmark result along with the run and commit it belongs to
+#[derive(Serialize, Debug, Clone)]
 pub struct Benchmark {
-    pub id: String,
-    pub created_at: String,
+    pub id: i64,
+    #[serde(with = "crate::time::rfc3339")]
+    pub created_at: NaiveDateTime,
     pub commit_hash: String,
     pub bench_name: String,
     pub mbps: f32,
     pub eps: f32,
     pub hist: String,
+    #[serde(with = "crate::time::rfc3339_opt")]
+    pub committed_at: Option<NaiveDateTime>,
+    pub author: Option<String>,
+    pub message: Option<String>,
+    pub status: String,
+    /// Custom metrics of the result, not part of `db::view()` and loaded
+    /// separately
+    pub metrics: Vec<MetricValue>,
 }
 
+type BenchmarkRow = (
+    i64,
+    NaiveDateTime,
+    String,
+    String,
+    f32,
+    f32,
+    String,
+    Option<NaiveDateTime>,
+    Option<String>,
+    Option<String>,
+    String,
+);
+
+impl<ST, DB> Queryable<ST, DB> for Benchmark
+where
+    DB: Backend,
+    BenchmarkRow: Queryable<ST, DB>,
+{
+    type Row = <BenchmarkRow as Queryable<ST, DB>>::Row;
+
+    fn build(row: Self::Row) -> Self {
+        let (
+            id,
+            created_at,
+            commit_hash,
+            bench_name,
+            mbps,
+            eps,
+            hist,
+            committed_at,
+            author,
+            message,
+            status,
+        ) = BenchmarkRow::build(row);
+        Self {
+            id,
+            created_at,
+            commit_hash,
+            bench_name,
+            mbps,
+            eps,
+            hist,
+            committed_at,
+            author,
+            message,
+            status,
+            metrics: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
 impl Benchmark {
-    pub fn as_new(&self) -> NewBenchmark {
-        NewBenchmark {
-            id: &self.id,
-            created_at: &self.created_at,
-            commit_hash: &self.commit_hash,
-            bench_name: &self.bench_name,
-            mbps: self.mbps,
-            eps: self.eps,
-            hist: &self.hist,
+    /// A passed `passthrough` result of `commit_hash` for tests
+    pub fn fixture(commit_hash: &str, eps: f32) -> Self {
+        Self {
+            id: 0,
+            created_at: NaiveDateTime::from_timestamp(0, 0),
+            commit_hash: commit_hash.to_string(),
+            bench_name: "passthrough".to_string(),
+            mbps: 0.0,
+            eps,
+            hist: String::new(),
+            committed_at: None,
+            author: None,
+            message: None,
+            status: ResultStatus::Passed.as_str().to_string(),
+            metrics: Vec::new(),
+        }
+    }
+}
+
+/// A commit along with what the push that contained it told us about it
+#[derive(Serialize, Queryable, Debug, Clone)]
+pub struct Commit {
+    pub sha: String,
+    pub author: Option<String>,
+    pub message: Option<String>,
+    #[serde(with = "crate::time::rfc3339_opt")]
+    pub committed_at: Option<NaiveDateTime>,
+    pub parent: Option<String>,
+    pub branch: Option<String>,
+    pub url: Option<String>,
+    pub push_id: Option<i64>,
+}
+
+/// Metadata of a commit, when updating a stored commit fields that are
+/// `None` are left as they are.
+#[derive(Insertable, AsChangeset, Debug, Clone, Default, PartialEq, Eq)]
+#[table_name = "commits"]
+pub struct CommitInfo {
+    pub author: Option<String>,
+    pub message: Option<String>,
+    pub committed_at: Option<NaiveDateTime>,
+    pub parent: Option<String>,
+    pub branch: Option<String>,
+    pub url: Option<String>,
+    pub push_id: Option<i64>,
+}
+
+#[derive(Serialize, Queryable, Debug, Clone)]
+pub struct Push {
+    pub id: i64,
+    pub branch: String,
+    pub before_sha: String,
+    pub after_sha: String,
+    pub compare_url: Option<String>,
+    pub pusher: Option<String>,
+    #[serde(with = "crate::time::rfc3339")]
+    pub received_at: NaiveDateTime,
+}
+
+/// A push along with the commits it contained, oldest first
+#[derive(Serialize, Debug, Clone)]
+pub struct PushDetails {
+    #[serde(flatten)]
+    pub push: Push,
+    pub commits: Vec<String>,
+}
+
+/// What `/commits/{sha}` returns
+#[derive(Serialize, Debug, Clone)]
+pub struct CommitDetails {
+    #[serde(flatten)]
+    pub commit: Commit,
+    pub push: Option<PushDetails>,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "pushes"]
+pub struct NewPush<'a> {
+    pub branch: &'a str,
+    pub before_sha: &'a str,
+    pub after_sha: &'a str,
+    pub compare_url: Option<&'a str>,
+    pub pusher: Option<&'a str>,
+    pub received_at: NaiveDateTime,
+}
+
+/// A benchmark result parsed from a report that has yet to be stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct Measurement {
+    pub bench_name: String,
+    pub mbps: f32,
+    pub eps: f32,
+    pub hist: String,
+    pub status: ResultStatus,
+    /// Why the result couldn't be parsed
+    pub error: Option<String>,
+    /// Output of the benchmark, only kept when it didn't pass
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    /// `output::VERSION` of the parser that read the result
+    pub parser_version: Option<i32>,
+    pub metrics: Vec<MetricValue>,
+}
+
+/// Which way a metric gets better
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Higher is better, like throughput
+    Higher,
+    /// Lower is better, like memory use or build time
+    Lower,
+}
+
+impl Direction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Higher => "higher",
+            Self::Lower => "lower",
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "higher" => Ok(Self::Higher),
+            "lower" => Ok(Self::Lower),
+            other => Err(format!(
+                "unknown direction `{}`, expected higher or lower",
+                other
+            )),
+        }
+    }
+}
+
+/// A named value of a result besides the throughput, e.g. peak RSS or
+/// binary size
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MetricValue {
+    pub name: String,
+    pub value: f64,
+    pub unit: String,
+    pub direction: Direction,
+}
+
+impl MetricValue {
+    pub fn new(name: &str, value: f64, unit: &str, direction: Direction) -> Self {
+        Self {
+            name: name.to_string(),
+            value,
+            unit: unit.to_string(),
+            direction,
+        }
+    }
+}
+
+/// Checks that the custom metrics of a result have unique, plain names that
+/// don't shadow the throughput, and finite values
+/// Checks the charset of a metric name, names end up in labels, paths and
+/// headers
+fn validate_metric_name(name: &str) -> Result<(), String> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./".contains(c))
+    {
+        return Err(format!(
+            "invalid metric name `{}`, use letters, digits, `_`, `-`, `.` and `/`",
+            name
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_metrics(metrics: &[MetricValue]) -> Result<(), String> {
+    for (i, m) in metrics.iter().enumerate() {
+        validate_metric_name(&m.name)?;
+        if !matches!(Metric::from(m.name.clone()), Metric::Custom(_)) {
+            return Err(format!("metric name `{}` is reserved", m.name));
+        }
+        if !m.value.is_finite() {
+            return Err(format!("metric `{}` is not a finite number", m.name));
+        }
+        if metrics[..i].iter().any(|o| o.name == m.name) {
+            return Err(format!("metric `{}` is given twice", m.name));
+        }
+    }
+    Ok(())
+}
+
+/// Outcome of a single benchmark, only passed results are part of trends
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultStatus {
+    Passed,
+    /// The report counted a failure
+    Failed,
+    /// The benchmark passed but its output couldn't be parsed
+    Unparseable,
+}
+
+impl ResultStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Passed => "passed",
+            Self::Failed => "failed",
+            Self::Unparseable => "unparseable",
         }
     }
 }
 
+impl FromStr for ResultStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "passed" => Ok(Self::Passed),
+            "failed" => Ok(Self::Failed),
+            "unparseable" => Ok(Self::Unparseable),
+            other => Err(format!("unknown result status `{}`", other)),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Running,
+    Finished,
+    Failed,
+}
+
+impl RunStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Finished => "finished",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// A run of the benchmarks of a commit
+#[derive(Serialize, Queryable, Debug, Clone)]
+pub struct Run {
+    pub id: i64,
+    pub commit_sha: String,
+    pub job_id: Option<i64>,
+    pub machine: Option<String>,
+    #[serde(with = "crate::time::rfc3339")]
+    pub started_at: NaiveDateTime,
+    #[serde(with = "crate::time::rfc3339_opt")]
+    pub finished_at: Option<NaiveDateTime>,
+    pub status: String,
+    pub allocator: Option<String>,
+    pub tremor_version: Option<String>,
+    pub librdkafka: Option<String>,
+    #[serde(skip)]
+    pub includes: Option<String>,
+    #[serde(skip)]
+    pub excludes: Option<String>,
+    /// What queued the run, only runs of `main` and backfills are part of
+    /// trends
+    pub source: String,
+}
+
+/// What `/runs` returns for each run
+#[derive(Serialize, Debug, Clone)]
+pub struct RunDetails {
+    #[serde(flatten)]
+    pub run: Run,
+    pub includes: Vec<String>,
+    pub excludes: Vec<String>,
+    pub suites: Vec<SuiteStats>,
+}
+
+/// Resource usage of the benchmark container at one point in time, the IO
+/// counters are totals since it started
+#[derive(Serialize, Queryable, Debug, Clone, PartialEq)]
+pub struct Sample {
+    #[serde(with = "crate::time::rfc3339")]
+    pub at: NaiveDateTime,
+    /// CPU use in percent of one core
+    pub cpu_percent: f64,
+    /// Memory use without the page cache
+    pub rss_bytes: i64,
+    pub read_bytes: i64,
+    pub write_bytes: i64,
+}
+
+#[derive(Insertable)]
+#[table_name = "run_samples"]
+pub struct NewSample {
+    pub run_id: i64,
+    pub at: NaiveDateTime,
+    pub cpu_percent: f64,
+    pub rss_bytes: i64,
+    pub read_bytes: i64,
+    pub write_bytes: i64,
+}
+
+/// A commit the queue skipped in favour of a newer one
+#[derive(Serialize, Queryable, Debug, Clone, PartialEq)]
+pub struct SkippedCommit {
+    pub commit_sha: String,
+    pub superseded_by: String,
+    #[serde(with = "crate::time::rfc3339")]
+    pub skipped_at: NaiveDateTime,
+    /// How often a backfill was tried without benchmarking the commit
+    pub backfill_attempts: i32,
+    #[serde(with = "crate::time::rfc3339_opt")]
+    pub backfilled_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[table_name = "skipped_commits"]
+pub struct NewSkippedCommit<'a> {
+    pub commit_sha: &'a str,
+    pub superseded_by: &'a str,
+    pub skipped_at: NaiveDateTime,
+}
+
+/// A parsed report: the build that was benchmarked, how the test suites
+/// went and the benchmark results
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub allocator: Option<String>,
+    pub tremor_version: Option<String>,
+    pub librdkafka: Option<String>,
+    pub includes: Vec<String>,
+    pub excludes: Vec<String>,
+    pub suites: Vec<SuiteStats>,
+    pub measurements: Vec<Measurement>,
+    /// Resource usage sampled while the benchmarks ran
+    pub samples: Vec<Sample>,
+}
+
+/// Totals of a test suite (`command`, `all`, `integration`, `unit` or
+/// `bench`) in a run
+#[derive(Serialize, Queryable, Debug, Clone, PartialEq, Eq)]
+pub struct SuiteStats {
+    pub suite: String,
+    pub pass: i32,
+    pub fail: i32,
+    pub skip: i32,
+    pub assertions: i32,
+}
+
 #[derive(Insertable)]
-#[table_name = "benchmarks"]
-pub struct NewBenchmark<'a> {
-    pub id: &'a str,
-    pub created_at: &'a str,
-    pub commit_hash: &'a str,
-    pub bench_name: &'a str,
+#[table_name = "suite_stats"]
+pub struct NewSuiteStats<'a> {
+    pub run_id: i64,
+    pub suite: &'a str,
+    pub pass: i32,
+    pub fail: i32,
+    pub skip: i32,
+    pub assertions: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "runs"]
+pub struct NewRun<'a> {
+    pub commit_sha: &'a str,
+    pub job_id: Option<i64>,
+    pub machine: Option<&'a str>,
+    pub started_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+    pub status: &'a str,
+    pub source: &'a str,
+}
+
+#[derive(Insertable)]
+#[table_name = "result_metrics"]
+pub struct NewResultMetric<'a> {
+    pub result_id: i64,
+    pub name: &'a str,
+    pub value: f64,
+    pub unit: &'a str,
+    pub direction: &'a str,
+}
+
+#[derive(Insertable)]
+#[table_name = "results"]
+pub struct NewResult<'a> {
+    pub run_id: i64,
+    pub benchmark_id: i64,
     pub mbps: f32,
     pub eps: f32,
     pub hist: &'a str,
+    pub status: &'a str,
+    pub error: Option<&'a str>,
+    pub stdout: Option<&'a str>,
+    pub stderr: Option<&'a str>,
+    pub parser_version: Option<i32>,
+}
+
+/// A result that didn't pass, with the output of the benchmark
+#[derive(Serialize, Queryable, Debug, Clone)]
+pub struct Failure {
+    pub id: i64,
+    pub run_id: i64,
+    #[serde(with = "crate::time::rfc3339")]
+    pub started_at: NaiveDateTime,
+    pub commit_hash: String,
+    pub bench_name: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+}
+
+/// A metric that can be charted or compared across commits: one of the
+/// throughputs every result has, or a custom metric by name
+#[derive(Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[serde(from = "String")]
+pub enum Metric {
+    #[default]
+    Eps,
+    Mbps,
+    Custom(String),
+}
+
+impl From<String> for Metric {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "eps" => Self::Eps,
+            "mbps" => Self::Mbps,
+            _ => Self::Custom(name),
+        }
+    }
+}
+
+impl Metric {
+    /// Checks a metric that was asked for by name
+    pub fn validate(&self) -> Result<(), String> {
+        validate_metric_name(self.name())
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Eps => "eps",
+            Self::Mbps => "mbps",
+            Self::Custom(name) => name,
+        }
+    }
+
+    /// The metric of a result, `None` if it wasn't recorded for it
+    pub fn get(&self, benchmark: &Benchmark) -> Option<MetricValue> {
+        match self {
+            Self::Eps => Some(MetricValue::new(
+                "eps",
+                f64::from(benchmark.eps),
+                "k events/s",
+                Direction::Higher,
+            )),
+            Self::Mbps => Some(MetricValue::new(
+                "mbps",
+                f64::from(benchmark.mbps),
+                "MB/s",
+                Direction::Higher,
+            )),
+            Self::Custom(name) => benchmark.metrics.iter().find(|m| &m.name == name).cloned(),
+        }
+    }
 }
@@ -0,0 +1,298 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parser for the stdout of a tremor benchmark: an HDR histogram followed
+//! by the data and event throughput.
+//!
+//! ```text
+//!      Value Percentile TotalCount 1/(1-Percentile)
+//!
+//!         1343 0.00000          1           1.00
+//! ...
+//! #[Buckets    =           30, SubBuckets     =         3968]
+//!
+//!
+//! Throughput   (data): 58.7 MB/s
+//! Throughput (events): 921.6k events/s
+//! ```
+//!
+//! The output can contain several such blocks, the last complete one is
+//! the result of the benchmark. A block with a throughput line that can't
+//! be parsed is dropped, its error is only returned when no complete block
+//! is left.
+
+use std::fmt;
+
+/// Stored with every result parsed by this module, bump it whenever the
+/// parser starts to read the same output differently.
+pub const VERSION: i32 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parsed {
+    /// Throughput in MB/s
+    pub mbps: f32,
+    /// Throughput in k events/s
+    pub eps: f32,
+    pub hist: String,
+    /// Number of throughput blocks in the output
+    pub blocks: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// A throughput line without a number
+    InvalidNumber {
+        line: usize,
+        text: String,
+    },
+    /// A throughput in a unit we don't know
+    UnknownUnit {
+        line: usize,
+        unit: String,
+    },
+    /// `data` or `events` throughput is missing
+    MissingThroughput(&'static str),
+    MissingHistogram,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidNumber { line, text } => {
+                write!(f, "line {}: invalid throughput `{}`", line, text)
+            }
+            Self::UnknownUnit { line, unit } => write!(f, "line {}: unknown unit `{}`", line, unit),
+            Self::MissingThroughput(kind) => write!(f, "no {} throughput found", kind),
+            Self::MissingHistogram => write!(f, "no histogram found"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Default)]
+struct Block {
+    hist: Vec<String>,
+    mbps: Option<f32>,
+    eps: Option<f32>,
+}
+
+impl Block {
+    fn is_empty(&self) -> bool {
+        self.hist.is_empty() && self.mbps.is_none() && self.eps.is_none()
+    }
+}
+
+enum Throughput {
+    Data(f32),
+    Events(f32),
+}
+
+/// Splits `58.7 MB/s` or `921.6k events/s` into the number and the unit
+fn split_value(s: &str) -> (&str, &str) {
+    let end = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .unwrap_or(s.len());
+    (&s[..end], s[end..].trim())
+}
+
+/// Parses the value of a `Throughput ...:` line, the label only tells the
+/// kind apart when the unit doesn't.
+fn throughput(line: usize, value: &str) -> Result<Throughput, ParseError> {
+    let (number, unit) = split_value(value);
+    let number: f32 = number.parse().map_err(|_| ParseError::InvalidNumber {
+        line,
+        text: value.to_string(),
+    })?;
+    let unknown = || ParseError::UnknownUnit {
+        line,
+        unit: unit.to_string(),
+    };
+    if let Some(prefix) = unit.strip_suffix("events/s") {
+        let factor = match prefix.trim() {
+            "" => 0.001,
+            "k" | "K" => 1.0,
+            "M" => 1_000.0,
+            "G" => 1_000_000.0,
+            _ => return Err(unknown()),
+        };
+        Ok(Throughput::Events(number * factor))
+    } else {
+        let factor = match unit {
+            "B/s" => 0.000_001,
+            "kB/s" | "KB/s" => 0.001,
+            "MB/s" => 1.0,
+            "GB/s" => 1_000.0,
+            _ => return Err(unknown()),
+        };
+        Ok(Throughput::Data(number * factor))
+    }
+}
+
+/// Parses the stdout of a benchmark
+pub fn parse(stdout: &str) -> Result<Parsed, ParseError> {
+    let mut blocks = Vec::new();
+    let mut block = Block::default();
+    let mut in_hist = false;
+    let mut error = None;
+
+    for (n, line) in stdout.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        let trimmed = line.trim();
+        if trimmed.starts_with("Value") && trimmed.contains("Percentile") {
+            if !block.is_empty() {
+                blocks.push(std::mem::take(&mut block));
+            }
+            in_hist = true;
+            block.hist.push(line.to_string());
+        } else if let Some(rest) = trimmed.strip_prefix("Throughput") {
+            in_hist = false;
+            let value = rest.split_once(':').map_or("", |(_, v)| v.trim());
+            match throughput(n + 1, value) {
+                Err(e) => {
+                    block = Block::default();
+                    error = Some(e);
+                }
+                Ok(Throughput::Data(v)) => {
+                    if block.mbps.is_some() {
+                        blocks.push(std::mem::take(&mut block));
+                    }
+                    block.mbps = Some(v);
+                }
+                Ok(Throughput::Events(v)) => {
+                    if block.eps.is_some() {
+                        blocks.push(std::mem::take(&mut block));
+                    }
+                    block.eps = Some(v);
+                }
+            }
+        } else if in_hist {
+            block.hist.push(line.to_string());
+            if trimmed.starts_with("#[Buckets") {
+                in_hist = false;
+            }
+        }
+    }
+    if !block.is_empty() {
+        blocks.push(block);
+    }
+
+    let count = blocks
+        .iter()
+        .filter(|b| b.mbps.is_some() || b.eps.is_some())
+        .count();
+    let last = blocks
+        .iter()
+        .rev()
+        .find(|b| b.mbps.is_some() && b.eps.is_some());
+    match last {
+        Some(b) if b.hist.is_empty() => Err(ParseError::MissingHistogram),
+        Some(b) => {
+            let mut hist = b.hist.clone();
+            while hist.last().is_some_and(|l| l.trim().is_empty()) {
+                hist.pop();
+            }
+            Ok(Parsed {
+                mbps: b.mbps.unwrap_or_default(),
+                eps: b.eps.unwrap_or_default(),
+                hist: hist.join("\n"),
+                blocks: count,
+            })
+        }
+        None => Err(match error {
+            Some(e) => e,
+            None if blocks.iter().any(|b| b.mbps.is_some()) => {
+                ParseError::MissingThroughput("events")
+            }
+            None => ParseError::MissingThroughput("data"),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hist;
+    use pretty_assertions::assert_eq;
+
+    macro_rules! fixture {
+        ($name:literal) => {
+            ($name, include_str!(concat!("../fixtures/stdout/", $name)))
+        };
+    }
+
+    /// Output of tremor benchmarks in the formats we have seen, and broken
+    /// variants of it
+    const FIXTURES: [(&str, &str); 11] = [
+        fixture!("current.txt"),
+        fixture!("mega_events.txt"),
+        fixture!("kilobytes.txt"),
+        fixture!("reordered.txt"),
+        fixture!("unlabeled.txt"),
+        fixture!("multiple_blocks.txt"),
+        fixture!("no_throughput.txt"),
+        fixture!("unknown_unit.txt"),
+        fixture!("bad_number.txt"),
+        fixture!("no_histogram.txt"),
+        fixture!("broken_first_block.txt"),
+    ];
+
+    fn expected(name: &str) -> Result<(f32, f32, usize), ParseError> {
+        match name {
+            "current.txt" | "reordered.txt" | "unlabeled.txt" => Ok((58.7, 921.6, 1)),
+            "mega_events.txt" => Ok((1200.0, 1500.0, 1)),
+            "kilobytes.txt" => Ok((0.512, 921.6, 1)),
+            "multiple_blocks.txt" => Ok((58.7, 921.6, 2)),
+            "broken_first_block.txt" => Ok((58.7, 921.6, 1)),
+            "no_throughput.txt" => Err(ParseError::MissingThroughput("data")),
+            "unknown_unit.txt" => Err(ParseError::UnknownUnit {
+                line: 13,
+                unit: "parsecs/s".to_string(),
+            }),
+            "bad_number.txt" => Err(ParseError::InvalidNumber {
+                line: 12,
+                text: "n/a MB/s".to_string(),
+            }),
+            "no_histogram.txt" => Err(ParseError::MissingHistogram),
+            other => panic!("no expectation for {}", other),
+        }
+    }
+
+    #[test]
+    fn test_fixtures() {
+        for (name, stdout) in FIXTURES {
+            let parsed = parse(stdout).map(|p| {
+                assert!(
+                    !hist::parse(&p.hist).is_empty(),
+                    "{}: empty histogram",
+                    name
+                );
+                let round = |v: f32| (v * 1000.0).round() / 1000.0;
+                (round(p.mbps), round(p.eps), p.blocks)
+            });
+            assert_eq!(parsed, expected(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_hist() -> Result<(), ParseError> {
+        let parsed = parse(&FIXTURES[0].1.replace('\n', "\r\n"))?;
+        assert!(parsed
+            .hist
+            .starts_with("     Value Percentile TotalCount 1/(1-Percentile)"));
+        assert!(parsed.hist.ends_with("SubBuckets     =         3968]"));
+        assert_eq!(hist::parse(&parsed.hist).len(), 53);
+        Ok(())
+    }
+}
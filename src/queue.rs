@@ -0,0 +1,294 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decides which of the queued jobs the worker runs. Jobs run by the
+//! priority of their source, pushes to main first. When main is pushed to
+//! faster than the benchmarks run, the commits in between can be skipped
+//! and are recorded for a later backfill.
+
+use crate::jobs::{Job, Source};
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    /// Every job is run
+    Fifo,
+    /// Of the pushes to main that queued up, only the newest and a sample
+    /// of evenly spaced others are run
+    Coalesce,
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fifo" => Ok(Self::Fifo),
+            "coalesce" => Ok(Self::Coalesce),
+            other => Err(format!(
+                "unknown queue policy `{}`, expected fifo or coalesce",
+                other
+            )),
+        }
+    }
+}
+
+/// Adds the jobs that queued up, oldest first, to the ones that are `ready`
+/// to run. Of all the pushes to main that are ready, the newest and up to
+/// `sample` of the others are kept. The ids of the kept ones are added to
+/// `sampled`, later calls never skip them. Returns the skipped jobs and the
+/// one that superseded them.
+pub fn schedule(
+    policy: Policy,
+    sample: usize,
+    ready: &mut VecDeque<Job>,
+    sampled: &mut HashSet<u64>,
+    waiting: Vec<Job>,
+) -> Option<(Job, Vec<Job>)> {
+    ready.extend(waiting);
+    sampled.retain(|id| ready.iter().any(|j| j.id == *id));
+    if policy == Policy::Fifo {
+        return None;
+    }
+    let (mut main, others): (Vec<Job>, Vec<Job>) = ready
+        .drain(..)
+        .partition(|j| j.source == Source::Main && !sampled.contains(&j.id));
+    ready.extend(others);
+    let newest = main.pop()?;
+    // sampled jobs that still wait count towards the sample
+    let picked = evenly_spaced(main.len(), sample.saturating_sub(sampled.len()));
+    let mut skipped = Vec::new();
+    // the kept jobs run oldest first, so a sampled one waits for at most
+    // `sample` others instead of every newer push
+    for (i, job) in main.into_iter().enumerate() {
+        if picked.contains(&i) {
+            sampled.insert(job.id);
+            ready.push_back(job);
+        } else {
+            skipped.push(job);
+        }
+    }
+    ready.push_back(newest.clone());
+    if skipped.is_empty() {
+        None
+    } else {
+        Some((newest, skipped))
+    }
+}
+
+/// Takes the job to run next out of the `ready` ones, the oldest of the
+/// source with the highest priority
+pub fn next(ready: &mut VecDeque<Job>) -> Option<Job> {
+    let (i, _) = ready.iter().enumerate().min_by_key(|(_, j)| j.source)?;
+    ready.remove(i)
+}
+
+/// Indices of `n` out of `len` items, spread evenly
+fn evenly_spaced(len: usize, n: usize) -> Vec<usize> {
+    if n >= len {
+        return (0..len).collect();
+    }
+    (1..=n).map(|i| i * len / (n + 1)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jobs::Jobs;
+    use pretty_assertions::assert_eq;
+
+    fn commits<'a>(jobs: impl IntoIterator<Item = &'a Job>) -> Vec<&'a str> {
+        jobs.into_iter().map(|j| j.commit_hash.as_str()).collect()
+    }
+
+    #[test]
+    fn test_schedule() {
+        let jobs = Jobs::default();
+        let waiting: Vec<Job> = ["a", "b", "c", "d", "e", "f"]
+            .iter()
+            .map(|c| jobs.enqueue(c, Source::Main, None))
+            .collect();
+
+        let (mut ready, mut sampled) = (VecDeque::new(), HashSet::new());
+        let res = schedule(Policy::Fifo, 0, &mut ready, &mut sampled, waiting.clone());
+        assert_eq!(res, None);
+        assert_eq!(commits(&ready), vec!["a", "b", "c", "d", "e", "f"]);
+
+        let (mut ready, mut sampled) = (VecDeque::new(), HashSet::new());
+        let res = schedule(
+            Policy::Coalesce,
+            0,
+            &mut ready,
+            &mut sampled,
+            waiting[..1].to_vec(),
+        );
+        assert_eq!(res, None);
+        assert_eq!(commits(&ready), vec!["a"]);
+
+        // jobs that are ready are superseded as well
+        let res = schedule(
+            Policy::Coalesce,
+            0,
+            &mut ready,
+            &mut sampled,
+            waiting[1..].to_vec(),
+        );
+        let (newest, skipped) = res.unwrap_or_else(|| panic!("nothing skipped"));
+        assert_eq!(newest.commit_hash, "f");
+        assert_eq!(commits(&skipped), vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(commits(&ready), vec!["f"]);
+
+        let (mut ready, mut sampled) = (VecDeque::new(), HashSet::new());
+        let res = schedule(
+            Policy::Coalesce,
+            1,
+            &mut ready,
+            &mut sampled,
+            waiting.clone(),
+        );
+        let skipped = res.map(|(_, skipped)| skipped).unwrap_or_default();
+        assert_eq!(commits(&skipped), vec!["a", "b", "d", "e"]);
+        assert_eq!(commits(&ready), vec!["c", "f"]);
+
+        let (mut ready, mut sampled) = (VecDeque::new(), HashSet::new());
+        let res = schedule(
+            Policy::Coalesce,
+            5,
+            &mut ready,
+            &mut sampled,
+            waiting.clone(),
+        );
+        assert_eq!(res, None);
+        assert_eq!(commits(&ready), vec!["a", "b", "c", "d", "e", "f"]);
+
+        assert_eq!(evenly_spaced(5, 2), vec![1, 3]);
+        assert_eq!(evenly_spaced(2, 3), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_batches() {
+        let jobs = Jobs::default();
+        let push = |commits: &[&str]| -> Vec<Job> {
+            commits
+                .iter()
+                .map(|c| jobs.enqueue(c, Source::Main, None))
+                .collect()
+        };
+        let (mut ready, mut sampled) = (VecDeque::new(), HashSet::new());
+        let res = schedule(
+            Policy::Coalesce,
+            1,
+            &mut ready,
+            &mut sampled,
+            push(&["a", "b", "c"]),
+        );
+        let skipped = res.map(|(_, skipped)| skipped).unwrap_or_default();
+        assert_eq!(commits(&skipped), vec!["a"]);
+        assert_eq!(commits(&ready), vec!["b", "c"]);
+
+        // a second batch before the runner picked up a job, the sampled job
+        // still waits so no other is sampled
+        let res = schedule(
+            Policy::Coalesce,
+            1,
+            &mut ready,
+            &mut sampled,
+            push(&["d", "e"]),
+        );
+        let (newest, skipped) = res.unwrap_or_else(|| panic!("nothing skipped"));
+        assert_eq!(newest.commit_hash, "e");
+        assert_eq!(commits(&skipped), vec!["c", "d"]);
+        assert_eq!(commits(&ready), vec!["b", "e"]);
+
+        // with a push for every job that runs, the sampled ones still run
+        let mut order = Vec::new();
+        for c in ["f", "g", "h"] {
+            order.extend(next(&mut ready).map(|j| j.commit_hash));
+            schedule(Policy::Coalesce, 1, &mut ready, &mut sampled, push(&[c]));
+        }
+        order.extend(std::iter::from_fn(|| next(&mut ready)).map(|j| j.commit_hash));
+        assert_eq!(order, vec!["b", "e", "f", "g", "h"]);
+    }
+
+    #[test]
+    fn test_sampled() {
+        let jobs = Jobs::default();
+        let push = |commits: &[&str]| -> Vec<Job> {
+            commits
+                .iter()
+                .map(|c| jobs.enqueue(c, Source::Main, None))
+                .collect()
+        };
+        let (mut ready, mut sampled) = (VecDeque::new(), HashSet::new());
+        schedule(
+            Policy::Coalesce,
+            2,
+            &mut ready,
+            &mut sampled,
+            push(&["a", "b", "c", "d"]),
+        );
+        assert_eq!(commits(&ready), vec!["b", "c", "d"]);
+
+        // the sampled jobs still wait, so they are neither skipped nor is
+        // another one sampled
+        let res = schedule(
+            Policy::Coalesce,
+            2,
+            &mut ready,
+            &mut sampled,
+            push(&["e", "f"]),
+        );
+        let skipped = res.map(|(_, skipped)| skipped).unwrap_or_default();
+        assert_eq!(commits(&skipped), vec!["d", "e"]);
+        assert_eq!(commits(&ready), vec!["b", "c", "f"]);
+
+        // once a sampled job ran, it no longer counts towards the sample
+        assert_eq!(
+            next(&mut ready).map(|j| j.commit_hash).as_deref(),
+            Some("b")
+        );
+        let res = schedule(
+            Policy::Coalesce,
+            2,
+            &mut ready,
+            &mut sampled,
+            push(&["g", "h"]),
+        );
+        let skipped = res.map(|(_, skipped)| skipped).unwrap_or_default();
+        assert_eq!(commits(&skipped), vec!["f"]);
+        assert_eq!(commits(&ready), vec!["c", "g", "h"]);
+    }
+
+    #[test]
+    fn test_priorities() {
+        let jobs = Jobs::default();
+        let waiting = vec![
+            jobs.enqueue("backfill", Source::Backfill, None),
+            jobs.enqueue("pr", Source::PullRequest, Some("octocat")),
+            jobs.enqueue("main-1", Source::Main, None),
+            jobs.enqueue("manual", Source::Manual, Some("octocat")),
+            jobs.enqueue("main-2", Source::Main, None),
+        ];
+        let (mut ready, mut sampled) = (VecDeque::new(), HashSet::new());
+        let res = schedule(Policy::Coalesce, 0, &mut ready, &mut sampled, waiting);
+        let skipped = res.map(|(_, skipped)| skipped).unwrap_or_default();
+        assert_eq!(commits(&skipped), vec!["main-1"]);
+
+        let order: Vec<String> = std::iter::from_fn(|| next(&mut ready))
+            .map(|j| j.commit_hash)
+            .collect();
+        assert_eq!(order, vec!["main-2", "manual", "pr", "backfill"]);
+    }
+}
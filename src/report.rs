@@ -0,0 +1,547 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The benchmark report formats we can ingest, each is mapped into a
+//! `Report`:
+//!
+//! - `tremor`: the report of `tremor test bench`
+//! - `criterion`: an `estimates.json` of criterion, or an object of them
+//!   keyed by benchmark name
+//! - `hyperfine`: the output of `hyperfine --export-json`
+//! - `generic`: `{"benchmarks": [{"name": "...", "eps": 1.0, "metrics": [...]}]}`
+//!
+//! Formats without a data throughput store an `mbps` of 0, `eps` is the
+//! number of iterations in thousands per second. Criterion and hyperfine
+//! results also keep the time per iteration as the `time` metric.
+
+use crate::error::Error;
+use crate::model::{validate_metrics, Direction, Measurement, MetricValue, Report, ResultStatus};
+use crate::util::convert_into_relevant_data;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Tremor,
+    Criterion,
+    Hyperfine,
+    Generic,
+}
+
+impl Format {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tremor => "tremor",
+            Self::Criterion => "criterion",
+            Self::Hyperfine => "hyperfine",
+            Self::Generic => "generic",
+        }
+    }
+
+    /// Guesses the format from the shape of the report
+    pub fn detect(report: &Value) -> Option<Self> {
+        let obj = report.as_object()?;
+        if obj.contains_key("reports") && obj.contains_key("stats") {
+            Some(Self::Tremor)
+        } else if obj.contains_key("benchmarks") {
+            Some(Self::Generic)
+        } else if obj
+            .get("results")
+            .and_then(Value::as_array)
+            .is_some_and(|r| r.iter().all(|r| r.get("command").is_some()))
+        {
+            Some(Self::Hyperfine)
+        } else if is_estimates(report) || (!obj.is_empty() && obj.values().all(is_estimates)) {
+            Some(Self::Criterion)
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tremor" => Ok(Self::Tremor),
+            "criterion" => Ok(Self::Criterion),
+            "hyperfine" => Ok(Self::Hyperfine),
+            "generic" => Ok(Self::Generic),
+            other => Err(format!(
+                "unknown report format `{}`, expected tremor, criterion, hyperfine or generic",
+                other
+            )),
+        }
+    }
+}
+
+fn is_estimates(v: &Value) -> bool {
+    v.get("mean")
+        .and_then(|m| m.get("point_estimate"))
+        .is_some()
+}
+
+/// Parses a report, `format` is detected when not given. `name` names the
+/// benchmark of a single criterion estimate and prefixes hyperfine commands.
+pub fn parse(data: &[u8], format: Option<Format>, name: Option<&str>) -> Result<Report, Error> {
+    let report: Value = serde_json::from_slice(data)
+        .map_err(|e| Error::Validation(format!("invalid report: {}", e)))?;
+    let format = format
+        .or_else(|| Format::detect(&report))
+        .ok_or_else(|| Error::Validation("unknown report format".to_string()))?;
+    let report = match format {
+        Format::Tremor => Ok(convert_into_relevant_data(deserialize(format, report)?)),
+        Format::Criterion => criterion(report, name),
+        Format::Hyperfine => hyperfine(deserialize(format, report)?, name),
+        Format::Generic => generic(deserialize(format, report)?),
+    }?;
+    for (i, m) in report.measurements.iter().enumerate() {
+        if report.measurements[..i]
+            .iter()
+            .any(|o| o.bench_name == m.bench_name)
+        {
+            return Err(Error::Validation(format!(
+                "benchmark `{}` is given twice",
+                m.bench_name
+            )));
+        }
+        for (name, value) in [("eps", m.eps), ("mbps", m.mbps)] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(Error::Validation(format!(
+                    "{}: invalid {} {}",
+                    m.bench_name, name, value
+                )));
+            }
+        }
+        validate_metrics(&m.metrics)
+            .map_err(|e| Error::Validation(format!("{}: {}", m.bench_name, e)))?;
+    }
+    Ok(report)
+}
+
+fn deserialize<T: DeserializeOwned>(format: Format, report: Value) -> Result<T, Error> {
+    T::deserialize(report)
+        .map_err(|e| Error::Validation(format!("invalid {} report: {}", format.name(), e)))
+}
+
+#[derive(Deserialize, Debug)]
+struct Estimates {
+    mean: Estimate,
+}
+
+#[derive(Deserialize, Debug)]
+struct Estimate {
+    /// Nanoseconds per iteration
+    point_estimate: f64,
+}
+
+/// Checks that the mean time per iteration of `bench_name` is a positive
+/// number, so the iterations a second are too
+fn mean(bench_name: &str, mean: f64) -> Result<f64, Error> {
+    if mean.is_finite() && mean > 0.0 {
+        Ok(mean)
+    } else {
+        Err(Error::Validation(format!(
+            "{}: invalid mean time {}",
+            bench_name, mean
+        )))
+    }
+}
+
+/// A measurement without data throughput that ran `per_sec` iterations a
+/// second
+fn measurement(
+    bench_name: String,
+    per_sec: f64,
+    hist: String,
+    metrics: Vec<MetricValue>,
+) -> Measurement {
+    Measurement {
+        bench_name,
+        mbps: 0.0,
+        eps: (per_sec / 1000.0) as f32,
+        hist,
+        status: ResultStatus::Passed,
+        error: None,
+        stdout: None,
+        stderr: None,
+        parser_version: None,
+        metrics,
+    }
+}
+
+fn criterion(report: Value, name: Option<&str>) -> Result<Report, Error> {
+    let estimates: BTreeMap<String, Estimates> = if is_estimates(&report) {
+        let name = name.ok_or_else(|| {
+            Error::Validation(
+                "a benchmark name is required for a single criterion estimate".to_string(),
+            )
+        })?;
+        std::iter::once((name.to_string(), deserialize(Format::Criterion, report)?)).collect()
+    } else {
+        deserialize(Format::Criterion, report)?
+    };
+    let measurements = estimates
+        .into_iter()
+        .map(|(name, e)| {
+            let ns = mean(&name, e.mean.point_estimate)?;
+            let time = MetricValue::new("time", ns, "ns", Direction::Lower);
+            Ok(measurement(name, 1e9 / ns, String::new(), vec![time]))
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok(Report {
+        measurements,
+        ..Report::default()
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct Hyperfine {
+    results: Vec<Command>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Command {
+    command: String,
+    /// Seconds per run
+    mean: f64,
+    /// User and system CPU time in seconds
+    user: Option<f64>,
+    system: Option<f64>,
+    #[serde(default)]
+    times: Vec<f64>,
+    #[serde(default)]
+    exit_codes: Vec<Option<i32>>,
+}
+
+/// The percentile distribution of the run times in microseconds, in the
+/// format of the histograms tremor prints
+fn distribution(times: &[f64]) -> String {
+    let mut micros: Vec<f64> = times.iter().map(|t| (t * 1e6).round()).collect();
+    micros.sort_by(|a, b| a.total_cmp(b));
+    let mut hist = String::from("     Value Percentile TotalCount 1/(1-Percentile)\n\n");
+    let total = micros.len();
+    for (i, value) in micros.iter().enumerate() {
+        let count = i + 1;
+        let percentile = count as f64 / total as f64;
+        // writing to a String can't fail
+        let _ = if count == total {
+            writeln!(
+                hist,
+                "{:>12} {:.6} {:>10} {:>14}",
+                value, percentile, count, "inf"
+            )
+        } else {
+            writeln!(
+                hist,
+                "{:>12} {:.6} {:>10} {:>14.2}",
+                value,
+                percentile,
+                count,
+                1.0 / (1.0 - percentile)
+            )
+        };
+    }
+    hist.trim_end().to_string()
+}
+
+fn hyperfine(report: Hyperfine, name: Option<&str>) -> Result<Report, Error> {
+    let single = report.results.len() == 1;
+    let measurements = report
+        .results
+        .into_iter()
+        .map(|c| {
+            let bench_name = match name {
+                Some(name) if single => name.to_string(),
+                Some(name) => format!("{}/{}", name, c.command),
+                None => c.command.clone(),
+            };
+            let seconds = mean(&bench_name, c.mean)?;
+            let failed = c.exit_codes.iter().filter(|c| **c != Some(0)).count();
+            let hist = if c.times.is_empty() {
+                String::new()
+            } else {
+                distribution(&c.times)
+            };
+            let ms = |name: &str, seconds: f64| {
+                MetricValue::new(name, seconds * 1000.0, "ms", Direction::Lower)
+            };
+            let metrics = std::iter::once(ms("time", seconds))
+                .chain(c.user.map(|t| ms("user_time", t)))
+                .chain(c.system.map(|t| ms("system_time", t)))
+                .collect();
+            let mut m = measurement(bench_name, 1.0 / seconds, hist, metrics);
+            if failed > 0 {
+                m.status = ResultStatus::Failed;
+                m.error = Some(format!("{} of {} runs failed", failed, c.exit_codes.len()));
+            }
+            Ok(m)
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok(Report {
+        measurements,
+        ..Report::default()
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct Generic {
+    benchmarks: Vec<GenericBench>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GenericBench {
+    name: String,
+    #[serde(default)]
+    mbps: f32,
+    #[serde(default)]
+    eps: f32,
+    #[serde(default)]
+    hist: String,
+    status: Option<String>,
+    error: Option<String>,
+    #[serde(default)]
+    metrics: Vec<MetricValue>,
+}
+
+fn generic(report: Generic) -> Result<Report, Error> {
+    let measurements = report
+        .benchmarks
+        .into_iter()
+        .map(|b| {
+            let status = match b.status.as_deref() {
+                Some(s) => ResultStatus::from_str(s).map_err(Error::Validation)?,
+                None => ResultStatus::Passed,
+            };
+            Ok(Measurement {
+                bench_name: b.name,
+                mbps: b.mbps,
+                eps: b.eps,
+                hist: b.hist,
+                status,
+                error: b.error,
+                stdout: None,
+                stderr: None,
+                parser_version: None,
+                metrics: b.metrics,
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok(Report {
+        measurements,
+        ..Report::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hist;
+    use hyper::StatusCode;
+    use pretty_assertions::assert_eq;
+
+    macro_rules! fixture {
+        ($name:literal) => {
+            include_bytes!(concat!("../fixtures/reports/", $name)).as_ref()
+        };
+    }
+
+    fn summary(report: &Report) -> Vec<(&str, f32, f32, ResultStatus)> {
+        report
+            .measurements
+            .iter()
+            .map(|m| {
+                let round = |v: f32| (v * 1000.0).round() / 1000.0;
+                (m.bench_name.as_str(), round(m.mbps), round(m.eps), m.status)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detect() -> Result<(), serde_json::Error> {
+        for (name, data, format) in [
+            ("tremor.json", fixture!("tremor.json"), Format::Tremor),
+            (
+                "estimates.json",
+                fixture!("estimates.json"),
+                Format::Criterion,
+            ),
+            (
+                "criterion.json",
+                fixture!("criterion.json"),
+                Format::Criterion,
+            ),
+            (
+                "hyperfine.json",
+                fixture!("hyperfine.json"),
+                Format::Hyperfine,
+            ),
+            ("generic.json", fixture!("generic.json"), Format::Generic),
+        ] {
+            let report = serde_json::from_slice(data)?;
+            assert_eq!(Format::detect(&report), Some(format), "{}", name);
+        }
+        assert_eq!(Format::detect(&serde_json::json!({"what": 1})), None);
+        assert_eq!(Format::detect(&serde_json::json!([])), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tremor() -> Result<(), Error> {
+        let report = parse(fixture!("tremor.json"), None, None)?;
+        assert_eq!(report.allocator.as_deref(), Some("snmalloc"));
+        assert_eq!(
+            summary(&report),
+            vec![("passthrough", 58.7, 921.6, ResultStatus::Passed)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_criterion() -> Result<(), Error> {
+        let single = fixture!("estimates.json");
+        assert!(matches!(
+            parse(single, None, None),
+            Err(Error::Validation(_))
+        ));
+        let report = parse(single, None, Some("value/parse"))?;
+        assert_eq!(
+            summary(&report),
+            vec![("value/parse", 0.0, 800.0, ResultStatus::Passed)]
+        );
+        assert_eq!(
+            report.measurements[0].metrics,
+            vec![MetricValue::new("time", 1250.0, "ns", Direction::Lower)]
+        );
+
+        let report = parse(fixture!("criterion.json"), Some(Format::Criterion), None)?;
+        assert_eq!(
+            summary(&report),
+            vec![
+                ("script/eval", 0.0, 2000.0, ResultStatus::Passed),
+                ("value/parse", 0.0, 800.0, ResultStatus::Passed),
+            ]
+        );
+
+        let zero = br#"{"mean": {"point_estimate": 0.0}}"#;
+        assert!(matches!(
+            parse(zero, None, Some("x")),
+            Err(Error::Validation(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_hyperfine() -> Result<(), Error> {
+        let data = fixture!("hyperfine.json");
+        let report = parse(data, None, None)?;
+        assert_eq!(
+            summary(&report),
+            vec![
+                ("tremor --version", 0.0, 0.1, ResultStatus::Passed),
+                ("tremor test bench", 0.0, 0.0, ResultStatus::Failed),
+            ]
+        );
+        assert_eq!(
+            report.measurements[1].error.as_deref(),
+            Some("1 of 2 runs failed")
+        );
+        let points = hist::parse(&report.measurements[0].hist);
+        assert_eq!(points.len(), 4);
+        assert_eq!(hist::percentile(&points, 0.5), Some(10_000.0));
+        assert_eq!(points[3].inverse, None);
+
+        let names: Vec<&str> = report.measurements[0]
+            .metrics
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["time", "user_time", "system_time"]);
+        assert_eq!(report.measurements[0].metrics[0].value, 10.0);
+
+        let report = parse(data, Some(Format::Hyperfine), Some("cli"))?;
+        assert_eq!(report.measurements[0].bench_name, "cli/tremor --version");
+
+        for mean in ["0", "-0.5"] {
+            let data = format!(r#"{{"results": [{{"command": "x", "mean": {}}}]}}"#, mean);
+            assert!(matches!(
+                parse(data.as_bytes(), None, None),
+                Err(Error::Validation(_))
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_generic() -> Result<(), Error> {
+        let report = parse(fixture!("generic.json"), None, None)?;
+        assert_eq!(
+            summary(&report),
+            vec![
+                ("pipeline/compile", 0.0, 12.5, ResultStatus::Passed),
+                ("codec/json", 120.0, 0.0, ResultStatus::Failed),
+            ]
+        );
+        assert_eq!(
+            report.measurements[0].metrics,
+            vec![MetricValue::new("peak_rss", 48.5, "MB", Direction::Lower)]
+        );
+        assert!(matches!(
+            parse(
+                br#"{"benchmarks": [{"name": "x", "status": "meh"}]}"#,
+                None,
+                None
+            ),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            parse(
+                br#"{"benchmarks": [{"name": "x", "metrics": [
+                    {"name": "eps", "value": 1, "unit": "", "direction": "higher"}]}]}"#,
+                None,
+                None
+            ),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            parse(br#"{"benchmarks": [{"name": "x", "eps": -1}]}"#, None, None),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            parse(
+                br#"{"benchmarks": [{"name": "x"}, {"name": "x"}]}"#,
+                None,
+                None
+            ),
+            Err(Error::Validation(_))
+        ));
+        // a bad report posted to the service is the client's fault
+        assert_eq!(
+            parse(b"{}", None, None).map_err(|e| e.status()),
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert!(matches!(parse(b"[", None, None), Err(Error::Validation(_))));
+        assert!(matches!(
+            parse(br#"{"benchmarks": 1}"#, None, None),
+            Err(Error::Validation(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Resource usage of the benchmark container, sampled with `docker stats`
+//! while it runs. The samples are stored with the run, their peak and
+//! average as the metrics of the `resources` benchmark.
+
+use crate::model::{Direction, Measurement, MetricValue, ResultStatus, Sample};
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+
+/// Name of the benchmark the resource metrics are stored with
+pub const BENCH: &str = "resources";
+
+/// The fields of `docker stats --format '{{json .}}'` we read
+#[derive(Deserialize, Debug)]
+struct Stats {
+    #[serde(rename = "CPUPerc")]
+    cpu: String,
+    #[serde(rename = "MemUsage")]
+    mem: String,
+    #[serde(rename = "BlockIO")]
+    block_io: String,
+}
+
+/// Parses sizes like `1.5GiB`, `3.4kB` or `0B` into bytes
+fn bytes(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let end = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let number: f64 = s[..end].parse().ok()?;
+    let factor = match s[end..].trim() {
+        "B" => 1.0,
+        "kB" | "KB" => 1e3,
+        "MB" => 1e6,
+        "GB" => 1e9,
+        "TB" => 1e12,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((number * factor).round() as i64)
+}
+
+/// Parses a line of `docker stats --no-stream --format '{{json .}}'`
+pub fn parse_stats(line: &str, at: NaiveDateTime) -> Option<Sample> {
+    let stats: Stats = serde_json::from_str(line.trim()).ok()?;
+    let cpu_percent = stats.cpu.trim().trim_end_matches('%').parse().ok()?;
+    let (used, _limit) = stats.mem.split_once('/')?;
+    let (read, write) = stats.block_io.split_once('/')?;
+    Some(Sample {
+        at,
+        cpu_percent,
+        rss_bytes: bytes(used)?,
+        read_bytes: bytes(read)?,
+        write_bytes: bytes(write)?,
+    })
+}
+
+/// The `resources` result of a run, `None` without samples
+pub fn measurement(samples: &[Sample]) -> Option<Measurement> {
+    let last = samples.last()?;
+    let n = samples.len() as f64;
+    let mb = |b: i64| b as f64 / 1_000_000.0;
+    let cpu = samples.iter().map(|s| s.cpu_percent);
+    let rss = samples.iter().map(|s| mb(s.rss_bytes));
+    let metrics = vec![
+        MetricValue::new(
+            "cpu_avg",
+            cpu.clone().sum::<f64>() / n,
+            "%",
+            Direction::Lower,
+        ),
+        MetricValue::new("cpu_peak", cpu.fold(0.0, f64::max), "%", Direction::Lower),
+        MetricValue::new(
+            "rss_avg",
+            rss.clone().sum::<f64>() / n,
+            "MB",
+            Direction::Lower,
+        ),
+        MetricValue::new("rss_peak", rss.fold(0.0, f64::max), "MB", Direction::Lower),
+        MetricValue::new("io_read", mb(last.read_bytes), "MB", Direction::Lower),
+        MetricValue::new("io_write", mb(last.write_bytes), "MB", Direction::Lower),
+    ];
+    Some(Measurement {
+        bench_name: BENCH.to_string(),
+        mbps: 0.0,
+        eps: 0.0,
+        hist: String::new(),
+        status: ResultStatus::Passed,
+        error: None,
+        stdout: None,
+        stderr: None,
+        parser_version: None,
+        metrics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::validate_metrics;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_stats() {
+        let at = NaiveDateTime::from_timestamp(0, 0);
+        let line = r#"{"BlockIO":"12.3MB / 4.1kB","CPUPerc":"183.52%","Container":"tremor-benchmark-abcdef","ID":"4f1c","MemPerc":"1.58%","MemUsage":"123.4MiB / 7.6GiB","Name":"tremor-benchmark-abcdef","NetIO":"1.2kB / 0B","PIDs":"24"}"#;
+        assert_eq!(
+            parse_stats(line, at),
+            Some(Sample {
+                at,
+                cpu_percent: 183.52,
+                rss_bytes: 129_394_278,
+                read_bytes: 12_300_000,
+                write_bytes: 4_100,
+            })
+        );
+        assert_eq!(parse_stats(r#"{"CPUPerc":"--"}"#, at), None);
+        assert_eq!(parse_stats("Error: No such container", at), None);
+        assert_eq!(bytes("0B"), Some(0));
+        assert_eq!(bytes("1.5GiB"), Some(1_610_612_736));
+        assert_eq!(bytes("12 parsecs"), None);
+    }
+
+    #[test]
+    fn test_measurement() {
+        let sample = |cpu: f64, rss: i64, read: i64| Sample {
+            at: NaiveDateTime::from_timestamp(0, 0),
+            cpu_percent: cpu,
+            rss_bytes: rss,
+            read_bytes: read,
+            write_bytes: 0,
+        };
+        assert_eq!(measurement(&[]), None);
+        let m = measurement(&[
+            sample(100.0, 2_000_000, 1_000_000),
+            sample(200.0, 6_000_000, 3_000_000),
+        ]);
+        let m = m.unwrap_or_else(|| panic!("no measurement"));
+        assert_eq!(m.bench_name, BENCH);
+        let values: Vec<(&str, f64)> = m
+            .metrics
+            .iter()
+            .map(|m| (m.name.as_str(), m.value))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ("cpu_avg", 150.0),
+                ("cpu_peak", 200.0),
+                ("rss_avg", 4.0),
+                ("rss_peak", 6.0),
+                ("io_read", 3.0),
+                ("io_write", 0.0),
+            ]
+        );
+        assert_eq!(validate_metrics(&m.metrics), Ok(()));
+    }
+}
@@ -1,11 +1,131 @@
 table! {
     benchmarks (id) {
-        id -> Text,
-        created_at -> Date,
-        commit_hash -> Text,
-        bench_name -> Text,
+        id -> BigInt,
+        name -> Text,
+    }
+}
+
+table! {
+    commits (sha) {
+        sha -> Text,
+        author -> Nullable<Text>,
+        message -> Nullable<Text>,
+        committed_at -> Nullable<Timestamp>,
+        parent -> Nullable<Text>,
+        branch -> Nullable<Text>,
+        url -> Nullable<Text>,
+        push_id -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    pushes (id) {
+        id -> BigInt,
+        branch -> Text,
+        before_sha -> Text,
+        after_sha -> Text,
+        compare_url -> Nullable<Text>,
+        pusher -> Nullable<Text>,
+        received_at -> Timestamp,
+    }
+}
+
+table! {
+    result_metrics (id) {
+        id -> BigInt,
+        result_id -> BigInt,
+        name -> Text,
+        value -> Double,
+        unit -> Text,
+        direction -> Text,
+    }
+}
+
+table! {
+    results (id) {
+        id -> BigInt,
+        run_id -> BigInt,
+        benchmark_id -> BigInt,
         mbps -> Float,
         eps -> Float,
         hist -> Text,
+        status -> Text,
+        error -> Nullable<Text>,
+        stdout -> Nullable<Text>,
+        stderr -> Nullable<Text>,
+        parser_version -> Nullable<Integer>,
+    }
+}
+
+table! {
+    run_samples (id) {
+        id -> BigInt,
+        run_id -> BigInt,
+        at -> Timestamp,
+        cpu_percent -> Double,
+        rss_bytes -> BigInt,
+        read_bytes -> BigInt,
+        write_bytes -> BigInt,
+    }
+}
+
+table! {
+    runs (id) {
+        id -> BigInt,
+        commit_sha -> Text,
+        job_id -> Nullable<BigInt>,
+        machine -> Nullable<Text>,
+        started_at -> Timestamp,
+        finished_at -> Nullable<Timestamp>,
+        status -> Text,
+        allocator -> Nullable<Text>,
+        tremor_version -> Nullable<Text>,
+        librdkafka -> Nullable<Text>,
+        includes -> Nullable<Text>,
+        excludes -> Nullable<Text>,
+        source -> Text,
+    }
+}
+
+table! {
+    skipped_commits (commit_sha) {
+        commit_sha -> Text,
+        superseded_by -> Text,
+        skipped_at -> Timestamp,
+        backfill_attempts -> Integer,
+        backfilled_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    suite_stats (id) {
+        id -> BigInt,
+        run_id -> BigInt,
+        suite -> Text,
+        pass -> Integer,
+        fail -> Integer,
+        skip -> Integer,
+        assertions -> Integer,
     }
 }
+
+joinable!(results -> benchmarks (benchmark_id));
+joinable!(results -> runs (run_id));
+joinable!(runs -> commits (commit_sha));
+joinable!(commits -> pushes (push_id));
+joinable!(result_metrics -> results (result_id));
+joinable!(run_samples -> runs (run_id));
+joinable!(skipped_commits -> commits (commit_sha));
+joinable!(suite_stats -> runs (run_id));
+
+allow_tables_to_appear_in_same_query!(
+    benchmarks,
+    commits,
+    pushes,
+    result_metrics,
+    results,
+    run_samples,
+    runs,
+    skipped_commits,
+    suite_stats,
+);
@@ -0,0 +1,113 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Timestamps are stored in UTC without an offset, as `TIMESTAMP` in
+//! PostgreSQL and as `YYYY-MM-DD HH:MM:SS.SSS` text in SQLite, and are
+//! exposed as RFC 3339 strings.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Timelike, Utc};
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+/// The current time, truncated to the microseconds PostgreSQL keeps so a
+/// stored time compares equal to the one it was written from
+pub fn now() -> NaiveDateTime {
+    let now = Utc::now().naive_utc();
+    now.with_nanosecond(now.nanosecond() / 1000 * 1000)
+        .unwrap_or(now)
+}
+
+/// Parses RFC 3339 timestamps with any offset, as well as the
+/// `2021-10-01 10:00:00.123 UTC` format older versions stored
+pub fn parse(s: &str) -> Option<NaiveDateTime> {
+    let s = s.trim();
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Some(t.naive_utc());
+    }
+    let naive = s.strip_suffix(" UTC").unwrap_or(s);
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(naive, f).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(naive, "%Y-%m-%d")
+                .ok()
+                .map(|d| d.and_hms(0, 0, 0))
+        })
+}
+
+/// Formats a UTC timestamp as RFC 3339, e.g. `2021-10-01T10:00:00.123Z`
+pub fn format(t: &NaiveDateTime) -> String {
+    DateTime::<Utc>::from_utc(*t, Utc).to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// `#[serde(with = "crate::time::rfc3339")]`
+pub mod rfc3339 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(t: &NaiveDateTime, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&format(t))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<NaiveDateTime, D::Error> {
+        let s = String::deserialize(d)?;
+        parse(&s).ok_or_else(|| de::Error::custom(format!("invalid timestamp `{}`", s)))
+    }
+}
+
+/// `#[serde(with = "crate::time::rfc3339_opt")]`, empty strings are `None`
+pub mod rfc3339_opt {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(t: &Option<NaiveDateTime>, s: S) -> Result<S::Ok, S::Error> {
+        match t {
+            Some(t) => s.serialize_some(&format(t)),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<NaiveDateTime>, D::Error> {
+        match Option::<String>::deserialize(d)? {
+            Some(s) if !s.is_empty() => parse(&s)
+                .map(Some)
+                .ok_or_else(|| de::Error::custom(format!("invalid timestamp `{}`", s))),
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse() {
+        let t = NaiveDate::from_ymd(2021, 10, 1).and_hms_milli(10, 0, 0, 123);
+        for s in [
+            "2021-10-01T10:00:00.123Z",
+            "2021-10-01T12:00:00.123+02:00",
+            "2021-10-01 10:00:00.123 UTC",
+            "2021-10-01 10:00:00.123",
+        ] {
+            assert_eq!(parse(s), Some(t), "{}", s);
+        }
+        assert_eq!(
+            parse("2021-10-01"),
+            Some(NaiveDate::from_ymd(2021, 10, 1).and_hms(0, 0, 0))
+        );
+        assert_eq!(parse("yesterday"), None);
+        assert_eq!(format(&t), "2021-10-01T10:00:00.123Z");
+        let now = now();
+        assert_eq!(parse(&format(&now)), Some(now));
+    }
+}
@@ -12,14 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use chrono::offset::Utc;
-use color_eyre::eyre::Result;
 use serde::Deserialize;
+use tracing::warn;
 
-use crate::error::Error;
+use crate::model::{Measurement, Report, ResultStatus, SuiteStats};
+use crate::output;
 
 // TODO The name is horrible here. pls help
-#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 pub struct WholeReport {
     metadata: Metadata,
@@ -58,7 +57,6 @@ struct Bench {
 }
 
 #[derive(Deserialize, Debug)]
-#[allow(dead_code)]
 struct Evidence {
     #[serde(rename = "test: stdout")]
     stdout: String,
@@ -80,7 +78,6 @@ struct Metadata {
 }
 
 #[derive(Deserialize, Debug)]
-#[allow(dead_code)]
 struct Stats {
     command: Stat,
     all: Stat,
@@ -90,7 +87,6 @@ struct Stats {
 }
 
 #[derive(Deserialize, Debug)]
-#[allow(dead_code)]
 struct Stat {
     pass: u16,
     fail: u16,
@@ -98,67 +94,95 @@ struct Stat {
     assert: u16,
 }
 
-pub fn convert_into_relevant_data(
-    whole_report: WholeReport,
-    commit_hash: &str,
-) -> Result<Vec<crate::model::Benchmark>> {
-    let created_at = Utc::now().to_string();
+impl Stats {
+    fn suites(&self) -> Vec<SuiteStats> {
+        [
+            ("command", &self.command),
+            ("all", &self.all),
+            ("integration", &self.integration),
+            ("unit", &self.unit),
+            ("bench", &self.bench),
+        ]
+        .iter()
+        .map(|(suite, stat)| SuiteStats {
+            suite: (*suite).to_string(),
+            pass: stat.pass.into(),
+            fail: stat.fail.into(),
+            skip: stat.skip.into(),
+            assertions: stat.assert.into(),
+        })
+        .collect()
+    }
+}
 
-    whole_report
+/// Extracts what's worth keeping from a report. Benchmarks that failed or
+/// whose output can't be parsed are kept along with their output.
+pub fn convert_into_relevant_data(whole_report: WholeReport) -> Report {
+    let measurements = whole_report
         .reports
         .bench
         .into_iter()
         .map(|report| {
-            // TODO add a check if benchmark has passed or failed
-            let r = &report.elements.bench.evidence.stdout;
-            let mbps = extract_throughput(r).ok_or(Error::Other("failed to get mbps"));
-            let eps = extract_events(r).ok_or(Error::Other("failed to get eps"));
-            let hist = extract_hist(r).ok_or(Error::Other("faild to get histogram"));
-
-            if mbps.is_err() || eps.is_err() || hist.is_err() {
-                dbg!(&report);
+            let bench = report.elements.bench;
+            let parsed = output::parse(&bench.evidence.stdout);
+            let status = if bench.stats.fail > 0 {
+                ResultStatus::Failed
+            } else if parsed.is_ok() {
+                ResultStatus::Passed
+            } else {
+                ResultStatus::Unparseable
+            };
+            let error = match status {
+                ResultStatus::Passed => None,
+                ResultStatus::Failed => Some(format!(
+                    "{} of {} checks failed",
+                    bench.stats.fail,
+                    u32::from(bench.stats.fail) + u32::from(bench.stats.pass)
+                )),
+                ResultStatus::Unparseable => parsed.as_ref().err().map(ToString::to_string),
+            };
+            // the output is stored with the result
+            if let Some(error) = &error {
+                warn!(
+                    bench = %bench.name,
+                    status = status.as_str(),
+                    %error,
+                    "benchmark did not pass"
+                );
             }
 
-            let mbps = mbps.unwrap_or_default();
-            let eps = eps.unwrap_or_default();
-            let hist = hist.unwrap_or_default().to_string();
+            let (mbps, eps, hist) =
+                parsed.map_or((0.0, 0.0, String::new()), |p| (p.mbps, p.eps, p.hist));
+            let (stdout, stderr) = if status == ResultStatus::Passed {
+                (None, None)
+            } else {
+                (Some(bench.evidence.stdout), Some(bench.evidence.stderr))
+            };
 
-            let bench_name = report.elements.bench.name;
-            Ok(crate::model::Benchmark {
-                id: format!("{}-{}-{}", commit_hash, &bench_name, &created_at),
-                created_at: created_at.clone(),
-                commit_hash: commit_hash.to_string(),
-                bench_name,
+            Measurement {
+                bench_name: bench.name,
                 mbps,
                 eps,
                 hist,
-            })
+                status,
+                error,
+                stdout,
+                stderr,
+                parser_version: Some(output::VERSION),
+                metrics: Vec::new(),
+            }
         })
-        .collect()
-}
-
-fn extract_throughput(log_string: &str) -> Option<f32> {
-    let start = log_string.find("Throughput   (data):")? + 21;
-    let end = log_string.find("MB/s")?;
-
-    log_string
-        .get(start..end)
-        .and_then(|throughput_string| throughput_string.trim().parse().ok())
-}
-
-fn extract_events(log_string: &str) -> Option<f32> {
-    let start = log_string.find("Throughput (events):")? + 21;
-    let end = log_string.find("k events/s")?;
-
-    log_string
-        .get(start..end)
-        .and_then(|throughput_string| throughput_string.trim().parse().ok())
-}
-
-fn extract_hist(log_string: &str) -> Option<&str> {
-    let end = log_string.find("\n\n\n")?;
-
-    log_string.get(..end)
+        .collect();
+    Report {
+        allocator: Some(whole_report.metadata.allocator),
+        tremor_version: Some(whole_report.metadata.version),
+        librdkafka: Some(whole_report.metadata.librdkafka),
+        includes: whole_report.includes,
+        excludes: whole_report.excludes,
+        suites: whole_report.stats.suites(),
+        measurements,
+        samples: Vec::new(),
+    }
 }
 
 #[cfg(test)]
@@ -167,136 +191,84 @@ mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
-    #[test]
-    fn test_extract_throughput() {
-        let some_log = r#"
-     Value Percentile TotalCount 1/(1-Percentile)
-
-        1343 0.00000          1           1.00
-        6495 0.25000   21519175           1.33
-        8895 0.50000   43774741           2.00
-       10111 0.62500   53834372           2.67
-       21247 0.75000   64466758           4.00
-       31615 0.81250   69841533           5.33
-       44031 0.87500   75268579           8.00
-       51199 0.90625   77931574          10.67
-       60159 0.93750   80644651          16.00
-       65279 0.95312   81924551          21.33
-       73215 0.96875   83285642          32.00
-       79871 0.97656   83938098          42.67
-       91135 0.98438   84624143          64.00
-       99839 0.98828   84958398          85.33
-      116735 0.99219   85280988         128.00
-      133119 0.99414   85457548         170.67
-      153599 0.99609   85618379         256.00
-      163839 0.99707   85709966         341.33
-      172031 0.99805   85789891         512.00
-      177151 0.99854   85827174         682.67
-      189439 0.99902   85869533        1024.00
-      203775 0.99927   85889457        1365.33
-      228351 0.99951   85910431        2048.00
-      246783 0.99963   85921094        2730.67
-      270335 0.99976   85931344        4096.00
-      288767 0.99982   85936712        5461.33
-      319487 0.99988   85941757        8192.00
-      342015 0.99991   85944442       10922.67
-      376831 0.99994   85947012       16384.00
-      397311 0.99995   85948245       21845.33
-      448511 0.99997   85949562       32768.00
-      473087 0.99998   85950208       43690.67
-      540671 0.99998   85950877       65536.00
-      561151 0.99999   85951202       87381.33
-      638975 0.99999   85951514      131072.00
-      679935 0.99999   85951685      174762.67
-      724991 1.00000   85951874      262144.00
-      749567 1.00000   85951940      349525.33
-      806911 1.00000   85951999      524288.00
-      815103 1.00000   85952066      699050.67
-      819199 1.00000   85952092     1048576.00
-      831487 1.00000   85952103     1398101.33
-      851967 1.00000   85952128     2097152.00
-      856063 1.00000   85952133     2796202.67
-      880639 1.00000   85952144     4194304.00
-      884735 1.00000   85952151     5592405.33
-      884735 1.00000   85952151     8388608.00
-      888831 1.00000   85952159    11184810.67
-      888831 1.00000   85952159    16777216.00
-      888831 1.00000   85952159    22369621.33
-      888831 1.00000   85952159    33554432.00
-      892927 1.00000   85952161    44739242.67
-      892927 1.00000   85952161            inf
-#[Mean       =     18564.86, StdDeviation   =     23434.73]
-#[Max        =       892927, Total count    =     85952161]
-#[Buckets    =           30, SubBuckets     =         3968]
-
-
-Throughput   (data): 58.7 MB/s
-Throughput (events): 921.6k events/s
-            "#;
+    fn report(benches: &[(&str, u16, &str)]) -> WholeReport {
+        let stat = |fail: u16| serde_json::json!({"pass": 1 - fail.min(1), "fail": fail, "skip": 0, "assert": 0});
+        let bench: Vec<serde_json::Value> = benches
+            .iter()
+            .map(|(name, fail, stdout)| {
+                serde_json::json!({
+                    "description": name,
+                    "elements": {"bench": {
+                        "name": name,
+                        "description": name,
+                        "elements": [],
+                        "evidence": {"test: stdout": stdout, "test: stderr": "oops"},
+                        "stats": stat(*fail),
+                        "duration": 1
+                    }}
+                })
+            })
+            .collect();
+        let metadata = serde_json::json!({
+            "allocator": "snmalloc", "repository": "", "description": "", "homepage": "",
+            "name": "tremor", "authors": "", "librdkafka": "1.7.0", "version": "0.11.12"
+        });
+        serde_json::from_value(serde_json::json!({
+            "metadata": metadata,
+            "includes": ["bench"],
+            "excludes": [],
+            "reports": {"bench": bench},
+            "stats": {"command": stat(0), "all": stat(0), "integration": stat(0), "unit": stat(0), "bench": stat(0)}
+        }))
+        .unwrap_or_else(|e| panic!("invalid report: {}", e))
+    }
 
-        let hist = r#"
-     Value Percentile TotalCount 1/(1-Percentile)
+    #[test]
+    fn test_convert_status() {
+        let ok = include_str!("../fixtures/stdout/current.txt");
+        let report = convert_into_relevant_data(report(&[
+            ("passed", 0, ok),
+            ("failed", 1, ok),
+            ("garbled", 0, "no numbers here"),
+        ]));
+        assert_eq!(report.allocator.as_deref(), Some("snmalloc"));
+        assert_eq!(report.librdkafka.as_deref(), Some("1.7.0"));
+        assert_eq!(report.includes, vec!["bench".to_string()]);
+        assert_eq!(report.suites.len(), 5);
+        assert_eq!(report.suites[0].suite, "command");
+        let measurements = report.measurements;
+        let status: Vec<ResultStatus> = measurements.iter().map(|m| m.status).collect();
+        assert_eq!(
+            status,
+            vec![
+                ResultStatus::Passed,
+                ResultStatus::Failed,
+                ResultStatus::Unparseable
+            ]
+        );
+        assert_eq!(measurements[0].eps, 921.6);
+        assert_eq!(measurements[0].stdout, None);
+        assert_eq!(measurements[0].parser_version, Some(output::VERSION));
+        assert_eq!(
+            measurements[1].error.as_deref(),
+            Some("1 of 1 checks failed")
+        );
+        assert_eq!(measurements[2].stderr.as_deref(), Some("oops"));
+        assert_eq!(
+            measurements[2].error.as_deref(),
+            Some("no data throughput found")
+        );
+    }
 
-        1343 0.00000          1           1.00
-        6495 0.25000   21519175           1.33
-        8895 0.50000   43774741           2.00
-       10111 0.62500   53834372           2.67
-       21247 0.75000   64466758           4.00
-       31615 0.81250   69841533           5.33
-       44031 0.87500   75268579           8.00
-       51199 0.90625   77931574          10.67
-       60159 0.93750   80644651          16.00
-       65279 0.95312   81924551          21.33
-       73215 0.96875   83285642          32.00
-       79871 0.97656   83938098          42.67
-       91135 0.98438   84624143          64.00
-       99839 0.98828   84958398          85.33
-      116735 0.99219   85280988         128.00
-      133119 0.99414   85457548         170.67
-      153599 0.99609   85618379         256.00
-      163839 0.99707   85709966         341.33
-      172031 0.99805   85789891         512.00
-      177151 0.99854   85827174         682.67
-      189439 0.99902   85869533        1024.00
-      203775 0.99927   85889457        1365.33
-      228351 0.99951   85910431        2048.00
-      246783 0.99963   85921094        2730.67
-      270335 0.99976   85931344        4096.00
-      288767 0.99982   85936712        5461.33
-      319487 0.99988   85941757        8192.00
-      342015 0.99991   85944442       10922.67
-      376831 0.99994   85947012       16384.00
-      397311 0.99995   85948245       21845.33
-      448511 0.99997   85949562       32768.00
-      473087 0.99998   85950208       43690.67
-      540671 0.99998   85950877       65536.00
-      561151 0.99999   85951202       87381.33
-      638975 0.99999   85951514      131072.00
-      679935 0.99999   85951685      174762.67
-      724991 1.00000   85951874      262144.00
-      749567 1.00000   85951940      349525.33
-      806911 1.00000   85951999      524288.00
-      815103 1.00000   85952066      699050.67
-      819199 1.00000   85952092     1048576.00
-      831487 1.00000   85952103     1398101.33
-      851967 1.00000   85952128     2097152.00
-      856063 1.00000   85952133     2796202.67
-      880639 1.00000   85952144     4194304.00
-      884735 1.00000   85952151     5592405.33
-      884735 1.00000   85952151     8388608.00
-      888831 1.00000   85952159    11184810.67
-      888831 1.00000   85952159    16777216.00
-      888831 1.00000   85952159    22369621.33
-      888831 1.00000   85952159    33554432.00
-      892927 1.00000   85952161    44739242.67
-      892927 1.00000   85952161            inf
-#[Mean       =     18564.86, StdDeviation   =     23434.73]
-#[Max        =       892927, Total count    =     85952161]
-#[Buckets    =           30, SubBuckets     =         3968]"#;
-        let throughput = Some(58.7);
-        let events = Some(921.6);
-        assert_eq!(extract_throughput(some_log), throughput);
-        assert_eq!(extract_events(some_log), events);
-        assert_eq!(extract_hist(some_log), Some(hist));
+    #[test]
+    fn test_convert_many_checks() {
+        let ok = include_str!("../fixtures/stdout/current.txt");
+        let mut many = report(&[("many", u16::MAX, ok)]);
+        many.reports.bench[0].elements.bench.stats.pass = u16::MAX;
+        let measurements = convert_into_relevant_data(many).measurements;
+        assert_eq!(
+            measurements[0].error.as_deref(),
+            Some("65535 of 131070 checks failed")
+        );
     }
 }
@@ -0,0 +1,212 @@
+"use strict";
+
+const REPO = "https://github.com/tremor-rs/tremor-runtime";
+const SVG = "http://www.w3.org/2000/svg";
+const WIDTH = 420;
+const HEIGHT = 180;
+const PAD = { top: 20, right: 10, bottom: 20, left: 50 };
+
+const METRICS = [
+  { key: "eps", title: "Throughput (k events/s)", value: (b) => b.eps },
+  { key: "mbps", title: "Throughput (MB/s)", value: (b) => b.mbps },
+  { key: "p99", title: "Latency p99", value: (b) => percentile(b.hist, 0.99) },
+];
+
+// Picks the value at the given percentile out of the HDR histogram text
+// stored alongside each benchmark.
+function percentile(hist, p) {
+  for (const line of hist.split("\n")) {
+    const cols = line.trim().split(/\s+/);
+    if (cols.length !== 4) continue;
+    const value = parseFloat(cols[0]);
+    const pct = parseFloat(cols[1]);
+    if (!isNaN(value) && !isNaN(pct) && pct >= p) return value;
+  }
+  return null;
+}
+
+// Charts for the custom metrics recorded for any run of a benchmark, in the
+// order they first show up
+function customMetrics(runs) {
+  const seen = new Map();
+  for (const b of runs) {
+    for (const m of b.metrics || []) {
+      if (!seen.has(m.name)) {
+        const better = m.direction === "lower" ? "lower is better" : "higher is better";
+        seen.set(m.name, {
+          key: m.name,
+          title: `${m.name} (${m.unit}, ${better})`,
+          value: (run) => {
+            const found = (run.metrics || []).find((o) => o.name === m.name);
+            return found ? found.value : null;
+          },
+        });
+      }
+    }
+  }
+  return [...seen.values()];
+}
+
+function el(name, attrs, text) {
+  const e = document.createElementNS(SVG, name);
+  for (const [k, v] of Object.entries(attrs || {})) e.setAttribute(k, v);
+  if (text !== undefined) e.textContent = text;
+  return e;
+}
+
+function short(hash) {
+  return hash.slice(0, 7);
+}
+
+// Timestamps from the API are RFC 3339 in UTC, shown in local time
+function when(ts) {
+  return ts ? new Date(ts).toLocaleString() : "";
+}
+
+function showTooltip(evt, text) {
+  const tip = document.getElementById("tooltip");
+  tip.textContent = text;
+  tip.style.left = `${evt.pageX + 12}px`;
+  tip.style.top = `${evt.pageY + 12}px`;
+  tip.hidden = false;
+}
+
+function hideTooltip() {
+  document.getElementById("tooltip").hidden = true;
+}
+
+// Who pushed what and when, for the tooltip of a point
+function pointText(metric, p) {
+  const lines = [`${metric.title}: ${p.v.toFixed(2)}`, `commit: ${short(p.b.commit_hash)}`];
+  if (p.b.message) lines.push(p.b.message.split("\n")[0]);
+  if (p.b.author) lines.push(`by ${p.b.author}`);
+  if (p.b.committed_at) lines.push(`committed at: ${when(p.b.committed_at)}`);
+  lines.push(`run at: ${when(p.b.created_at)}`);
+  return lines.join("\n");
+}
+
+function chart(metric, runs) {
+  const points = runs
+    .map((b) => ({ b, v: metric.value(b) }))
+    .filter((p) => p.v !== null);
+  const svg = el("svg", { class: "chart", width: WIDTH, height: HEIGHT });
+  svg.appendChild(el("text", { class: "title", x: PAD.left, y: 14 }, metric.title));
+  if (points.length === 0) {
+    svg.appendChild(el("text", { x: PAD.left, y: HEIGHT / 2 }, "no data"));
+    return svg;
+  }
+
+  const max = Math.max(...points.map((p) => p.v)) || 1;
+  const w = WIDTH - PAD.left - PAD.right;
+  const h = HEIGHT - PAD.top - PAD.bottom;
+  const x = (i) => PAD.left + (points.length === 1 ? w / 2 : (i * w) / (points.length - 1));
+  const y = (v) => PAD.top + h - (v / max) * h;
+
+  svg.appendChild(el("line", { x1: PAD.left, y1: PAD.top + h, x2: PAD.left + w, y2: PAD.top + h, stroke: "#d0d7de" }));
+  svg.appendChild(el("text", { x: 2, y: PAD.top + 4 }, max.toFixed(1)));
+  svg.appendChild(el("text", { x: 2, y: PAD.top + h }, "0"));
+  svg.appendChild(el("text", { x: PAD.left, y: HEIGHT - 4 }, short(points[0].b.commit_hash)));
+  svg.appendChild(
+    el("text", { x: PAD.left + w, y: HEIGHT - 4, "text-anchor": "end" }, short(points[points.length - 1].b.commit_hash))
+  );
+  svg.appendChild(el("polyline", { points: points.map((p, i) => `${x(i)},${y(p.v)}`).join(" ") }));
+
+  points.forEach((p, i) => {
+    const link = el("a", { href: `${REPO}/commit/${p.b.commit_hash}`, target: "_blank" });
+    const dot = el("circle", { cx: x(i), cy: y(p.v), r: 3 });
+    const text = pointText(metric, p);
+    dot.addEventListener("mousemove", (evt) => showTooltip(evt, text));
+    dot.addEventListener("mouseleave", hideTooltip);
+    link.appendChild(dot);
+    svg.appendChild(link);
+  });
+  return svg;
+}
+
+function renderBenchmarks(rows) {
+  const byName = new Map();
+  for (const b of rows) {
+    if (!byName.has(b.bench_name)) byName.set(b.bench_name, []);
+    byName.get(b.bench_name).push(b);
+  }
+
+  const root = document.getElementById("benchmarks");
+  root.replaceChildren();
+  for (const name of [...byName.keys()].sort()) {
+    const section = document.createElement("div");
+    section.className = "bench";
+    const title = document.createElement("h3");
+    title.textContent = name;
+    const charts = document.createElement("div");
+    charts.className = "charts";
+    const runs = byName.get(name);
+    // benchmarks like `build` only have custom metrics, skip the empty throughput charts
+    const builtin = METRICS.filter((m) => runs.some((b) => m.value(b)));
+    for (const metric of builtin.concat(customMetrics(runs))) charts.appendChild(chart(metric, runs));
+    section.append(title, charts);
+    root.appendChild(section);
+  }
+}
+
+function renderJobs(jobs) {
+  const body = document.querySelector("#jobs tbody");
+  body.replaceChildren();
+  for (const job of jobs.reverse()) {
+    const tr = document.createElement("tr");
+    const commit = document.createElement("a");
+    commit.href = `${REPO}/commit/${job.commit_hash}`;
+    commit.textContent = short(job.commit_hash);
+    const note = job.superseded_by ? `superseded by ${short(job.superseded_by)}` : job.cancel_reason || job.error;
+    const source = job.author ? `${job.source} (${job.author})` : job.source;
+    const cells = [job.id, commit, source, job.status, when(job.queued_at), when(job.started_at), when(job.finished_at), note];
+    for (const c of cells) {
+      const td = document.createElement("td");
+      if (c instanceof Node) td.appendChild(c);
+      else td.textContent = c === null || c === undefined ? "" : c;
+      tr.appendChild(td);
+    }
+    tr.children[3].className = `status-${job.status}`;
+    body.appendChild(tr);
+  }
+}
+
+// Suite totals as `unit 120/122`, passed out of run
+function suites(run) {
+  return run.suites.map((s) => `${s.suite} ${s.pass}/${s.pass + s.fail}`).join(", ");
+}
+
+function renderRuns(runs) {
+  const body = document.querySelector("#runs tbody");
+  body.replaceChildren();
+  for (const run of runs) {
+    const tr = document.createElement("tr");
+    const commit = document.createElement("a");
+    commit.href = `${REPO}/commit/${run.commit_sha}`;
+    commit.textContent = short(run.commit_sha);
+    const cells = [run.id, commit, run.status, when(run.started_at), run.tremor_version, run.allocator, run.librdkafka, suites(run)];
+    for (const c of cells) {
+      const td = document.createElement("td");
+      if (c instanceof Node) td.appendChild(c);
+      else td.textContent = c === null || c === undefined ? "" : c;
+      tr.appendChild(td);
+    }
+    tr.children[2].className = `status-${run.status}`;
+    if (run.suites.some((s) => s.fail > 0)) tr.children[7].className = "status-failed";
+    body.appendChild(tr);
+  }
+}
+
+async function refresh() {
+  try {
+    const [bench, jobs, runs] = await Promise.all([fetch("bench"), fetch("jobs"), fetch("runs?last=20")]);
+    renderBenchmarks(await bench.json());
+    renderJobs(await jobs.json());
+    renderRuns(await runs.json());
+    document.getElementById("updated").textContent = `updated ${new Date().toLocaleTimeString()}`;
+  } catch (e) {
+    document.getElementById("updated").textContent = `update failed: ${e}`;
+  }
+}
+
+refresh();
+setInterval(refresh, 10000);